@@ -0,0 +1,88 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::chacha20poly1305::{XChaCha20Poly1305, Key, XNonce};
+use super::chacha20poly1305::aead::{Aead, Error as AeadError, KeyInit, Payload};
+use super::safe_vec::SafeVec;
+
+/// Length of the XChaCha20-Poly1305 key.
+pub const KEY_LEN: usize = 32;
+
+/// Length of the XChaCha20-Poly1305 nonce.
+///
+/// The extended nonce is 192 bits long, which makes it safe to pick at random
+/// every time the file is written.
+pub const NONCE_LEN: usize = 24;
+
+// Encrypts and authenticates a buffer with XChaCha20-Poly1305.
+//
+// The associated data is not encrypted, but any change to it will make
+// decryption fail.
+pub fn encrypt(data: &[u8], key: &[u8], nonce: &[u8], aad: &[u8]) -> Result<Vec<u8>, AeadError> {
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(key));
+    cipher.encrypt(XNonce::from_slice(nonce),
+                   Payload {
+                       msg: data,
+                       aad: aad,
+                   })
+}
+
+// Checks the authentication tag and decrypts a buffer with XChaCha20-Poly1305.
+//
+// An error here means either the key is wrong, or the ciphertext or the
+// associated data have been tampered with. There is no way to tell which.
+pub fn decrypt(encrypted_data: &[u8],
+               key: &[u8],
+               nonce: &[u8],
+               aad: &[u8])
+               -> Result<SafeVec, AeadError> {
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(key));
+    cipher.decrypt(XNonce::from_slice(nonce),
+                 Payload {
+                     msg: encrypted_data,
+                     aad: aad,
+                 })
+        .map(SafeVec::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ops::Deref;
+
+    const KEY: [u8; KEY_LEN] = [7u8; KEY_LEN];
+    const NONCE: [u8; NONCE_LEN] = [9u8; NONCE_LEN];
+
+    #[test]
+    fn decrypt_gives_back_what_was_encrypted() {
+        let encrypted = encrypt(b"secret", &KEY, &NONCE, b"header").unwrap();
+        assert_eq!(encrypted.len(), 6 + 16);
+        assert_eq!(decrypt(&encrypted, &KEY, &NONCE, b"header").unwrap().deref(), b"secret");
+    }
+
+    #[test]
+    fn decrypt_fails_when_anything_changes() {
+        let encrypted = encrypt(b"secret", &KEY, &NONCE, b"header").unwrap();
+
+        for i in 0..encrypted.len() {
+            let mut tampered = encrypted.clone();
+            tampered[i] ^= 1;
+            assert!(decrypt(&tampered, &KEY, &NONCE, b"header").is_err());
+        }
+        assert!(decrypt(&encrypted, &KEY, &NONCE, b"Header").is_err());
+        assert!(decrypt(&encrypted, &KEY, &NONCE, b"").is_err());
+        assert!(decrypt(&encrypted, &[8u8; KEY_LEN], &NONCE, b"header").is_err());
+        assert!(decrypt(&encrypted, &KEY, &[10u8; NONCE_LEN], b"header").is_err());
+    }
+}
@@ -0,0 +1,227 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::timestamp::{self, Timestamp};
+use std::cmp;
+use std::env;
+use std::fs::{self, File};
+use std::io::{Error as IoError, ErrorKind as IoErrorKind, Read, Result as IoResult, Write};
+use std::path::{Path, PathBuf};
+
+/// Environment variable telling how many backups to keep. 0 turns backups off.
+pub const RETENTION_ENV_VAR: &'static str = "ROOSTER_BACKUPS";
+
+/// How many backups to keep when the environment variable isn't set.
+const DEFAULT_RETENTION: usize = 10;
+
+/// Length of the timestamp backups are named after, like `2017-03-01T10-00-00`.
+const TIMESTAMP_LEN: usize = 19;
+
+/// A copy of the password file, as it was before it was saved at some point.
+///
+/// Backups are encrypted like the password file, since they are just older copies of it.
+pub struct Backup {
+    /// When the backup was made, in UTC, for instance `2017-03-01T10-00-00`.
+    pub id: String,
+    pub path: PathBuf,
+}
+
+impl Backup {
+    pub fn read(&self) -> IoResult<Vec<u8>> {
+        let mut contents = Vec::new();
+        File::open(&self.path)?.read_to_end(&mut contents)?;
+        Ok(contents)
+    }
+}
+
+/// How many backups to keep, from the environment. `None` if the variable isn't a number.
+pub fn get_retention() -> Option<usize> {
+    match env::var(RETENTION_ENV_VAR) {
+        Ok(retention) => retention.parse::<usize>().ok(),
+        Err(_) => Some(DEFAULT_RETENTION),
+    }
+}
+
+/// The directory backups of the password file at `path` go to, `<path>.bak`.
+pub fn backup_directory(path: &Path) -> IoResult<PathBuf> {
+    let file_name = match path.file_name() {
+        Some(file_name) => file_name.to_string_lossy().into_owned(),
+        None => return Err(IoError::new(IoErrorKind::Other, "invalid password file path")),
+    };
+    Ok(path.with_file_name(format!("{}.bak", file_name)))
+}
+
+/// Lists the backups of the password file at `path`, oldest first.
+pub fn list_backups(path: &Path) -> IoResult<Vec<Backup>> {
+    let directory = backup_directory(path)?;
+    if !directory.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(&directory)? {
+        let entry = entry?;
+        if let Ok(id) = entry.file_name().into_string() {
+            backups.push(Backup {
+                id: id,
+                path: entry.path(),
+            });
+        }
+    }
+    backups.sort_by(|a, b| id_order(&a.id).cmp(&id_order(&b.id)));
+    Ok(backups)
+}
+
+/// Ids are timestamps, followed by a number for backups made in the same second. They are
+/// ordered by timestamp, then by that number, so `-10` comes after `-2`.
+fn id_order(id: &str) -> (&str, u64) {
+    match id.rfind('-') {
+        Some(i) if i > TIMESTAMP_LEN - 1 => {
+            match id[i + 1..].parse::<u64>() {
+                Ok(suffix) => (&id[..i], suffix),
+                Err(_) => (id, 0),
+            }
+        }
+        _ => (id, 0),
+    }
+}
+
+/// Saves `contents`, the current contents of the password file at `path`, as a new backup.
+///
+/// Then, only the `retention` most recent backups are kept.
+pub fn create_backup(path: &Path, contents: &[u8], retention: usize) -> IoResult<()> {
+    if retention == 0 {
+        return Ok(());
+    }
+
+    let directory = backup_directory(path)?;
+    create_backup_directory(&directory)?;
+
+    // Backups made in the same second get a suffix after that of the last one, so neither is
+    // lost, and they stay in order even once the first ones are removed.
+    let timestamp = format_timestamp(timestamp::now());
+    let last_suffix = list_backups(path)?
+        .iter()
+        .filter(|backup| id_order(&backup.id).0 == timestamp)
+        .map(|backup| cmp::max(id_order(&backup.id).1, 1))
+        .max();
+    let backup_path = match last_suffix {
+        Some(suffix) => directory.join(format!("{}-{}", timestamp, suffix + 1)),
+        None => directory.join(&timestamp),
+    };
+
+    let mut file = File::create(&backup_path)?;
+    if let Ok(metadata) = fs::metadata(path) {
+        fs::set_permissions(&backup_path, metadata.permissions())?;
+    }
+    file.write_all(contents)?;
+    file.sync_all()?;
+
+    let backups = list_backups(path)?;
+    if backups.len() > retention {
+        for backup in backups[..backups.len() - retention].iter() {
+            fs::remove_file(&backup.path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Backups are only readable by their owner, like the password file should be.
+#[cfg(unix)]
+fn create_backup_directory(directory: &Path) -> IoResult<()> {
+    use std::fs::DirBuilder;
+    use std::os::unix::fs::DirBuilderExt;
+
+    if directory.exists() {
+        return Ok(());
+    }
+    DirBuilder::new().mode(0o700).create(directory)
+}
+
+#[cfg(not(unix))]
+fn create_backup_directory(directory: &Path) -> IoResult<()> {
+    if directory.exists() {
+        return Ok(());
+    }
+    fs::create_dir(directory)
+}
+
+/// Formats a timestamp as a UTC date that can be used in a file name, for instance
+/// `2017-03-01T10-00-00`.
+fn format_timestamp(timestamp: Timestamp) -> String {
+    let (year, month, day, hours, minutes, seconds) = timestamp::to_utc(timestamp);
+    format!("{:04}-{:02}-{:02}T{:02}-{:02}-{:02}",
+            year,
+            month,
+            day,
+            hours,
+            minutes,
+            seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    /// A directory of its own for each test, so tests can run in parallel.
+    fn test_directory(name: &str) -> PathBuf {
+        let directory = env::temp_dir().join(format!("rooster-test-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn ids_are_ordered_by_when_backups_were_made() {
+        let mut ids = vec!["2017-03-01T10-00-00-10",
+                           "2017-03-01T10-00-01",
+                           "2017-03-01T10-00-00-2",
+                           "2017-03-01T10-00-00",
+                           "2016-12-31T23-59-59-3"];
+        ids.sort_by(|a, b| id_order(a).cmp(&id_order(b)));
+        assert_eq!(ids,
+                   vec!["2016-12-31T23-59-59-3",
+                        "2017-03-01T10-00-00",
+                        "2017-03-01T10-00-00-2",
+                        "2017-03-01T10-00-00-10",
+                        "2017-03-01T10-00-01"]);
+        assert_eq!(format_timestamp(0).len(), TIMESTAMP_LEN);
+    }
+
+    #[test]
+    fn create_backup_keeps_the_most_recent_ones() {
+        let directory = test_directory("backups");
+        let path = directory.join("passwords.rooster");
+
+        // Most of these are made in the same second, so they go past a suffix of 10.
+        for i in 0..12 {
+            create_backup(&path, format!("{}", i).as_bytes(), 10).unwrap();
+        }
+
+        let contents: Vec<String> = list_backups(&path)
+            .unwrap()
+            .iter()
+            .map(|backup| String::from_utf8(backup.read().unwrap()).unwrap())
+            .collect();
+        let expected: Vec<String> = (2..12).map(|i| format!("{}", i)).collect();
+        assert_eq!(contents, expected);
+
+        create_backup(&path, b"off", 0).unwrap();
+        assert_eq!(list_backups(&path).unwrap().len(), 10);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,70 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::super::getopts;
+use super::super::password;
+use super::choose_password;
+use std::io::Write;
+use std::ops::Deref;
+
+pub fn callback_help() {
+    println!("Usage:");
+    println!("    rooster abort -h");
+    println!("    rooster abort <app_name>");
+    println!("");
+    println!("Drops the password staged by `rooster regenerate --staged`, when the app didn't");
+    println!("accept it. The current password stays as it is.");
+    println!("");
+    println!("Example:");
+    println!("    rooster abort youtube");
+}
+
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::PasswordStore)
+                     -> Result<(), i32> {
+    if matches.free.len() < 2 {
+        println_err!("Woops, seems like the app name is missing here. For help, try:");
+        println_err!("    rooster abort -h");
+        return Err(1);
+    }
+
+    let app_name = matches.free[1].clone();
+
+    let current = choose_password(store, app_name.deref())?;
+    if current.pending.is_none() {
+        println_err!("Woops, \"{}\" has no pending password to abort.", app_name);
+        return Err(1);
+    }
+
+    let change_result = store.change_password(current.name.deref(),
+                                              current.username.deref(),
+                                              &|old_password: password::Password| {
+        let mut new_password = old_password.clone();
+        new_password.pending = None;
+        new_password
+    });
+
+    match change_result {
+        Ok(()) => {
+            println_ok!("Done! I've dropped the pending password for \"{}\", the current one \
+                         stays.",
+                        app_name);
+            Ok(())
+        }
+        Err(err) => {
+            println_err!("Woops, I couldn't drop the pending password (reason: {:?}).", err);
+            Err(1)
+        }
+    }
+}
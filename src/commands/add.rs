@@ -17,20 +17,26 @@ use super::super::password;
 use super::super::rpassword::prompt_password_stderr;
 use super::super::safe_string::SafeString;
 use super::super::clipboard::{copy_to_clipboard, paste_keys};
+use super::fields_from_matches;
 use std::io::Write;
 use std::ops::Deref;
 
 pub fn callback_help() {
     println!("Usage:");
     println!("    rooster add -h");
-    println!("    rooster add <app_name> <username>");
+    println!("    rooster add <app_name> <username> [--field <name>=<value> ...] \
+              [--secret-field <name> ...]");
+    println!("");
+    println!("Custom fields keep anything else about the account, like its URL. The values of");
+    println!("secret fields are asked for, like the password.");
     println!("");
     println!("Example:");
     println!("    rooster add YouTube me@example.com");
+    println!("    rooster add Bank 1234567 --field url=https://bank.example.com --secret-field pin");
 }
 
 pub fn callback_exec(matches: &getopts::Matches,
-                     store: &mut password::v2::PasswordStore)
+                     store: &mut password::PasswordStore)
                      -> Result<(), i32> {
     if matches.free.len() < 3 {
         println_err!("Woops, seems like the app name or the username is missing here. For help, \
@@ -42,18 +48,21 @@ pub fn callback_exec(matches: &getopts::Matches,
     let app_name = matches.free[1].clone();
     let username = matches.free[2].clone();
 
-    if store.has_password(app_name.deref()) {
-        println_err!("Woops, there is already an app with that name.");
+    if store.has_account(app_name.deref(), username.deref()) {
+        println_err!("Woops, there is already an account with that app name and username.");
         return Err(1);
     }
 
+    let fields = fields_from_matches(matches)?;
+
     match prompt_password_stderr(format!("What password do you want for \"{}\"? ", app_name)
         .as_str()) {
         Ok(password_as_string) => {
             let password_as_string_clipboard = SafeString::new(password_as_string.clone());
-            let password = password::v2::Password::new(app_name.clone(),
+            let mut password = password::Password::new(app_name.clone(),
                                                        username,
                                                        SafeString::new(password_as_string));
+            password.fields = fields.into_iter().filter(|f| !f.value.is_empty()).collect();
             match store.add_password(password) {
                 Ok(_) => {
                     if matches.opt_present("show") {
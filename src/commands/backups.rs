@@ -0,0 +1,200 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::super::backup::{self, Backup};
+use super::super::getopts;
+use super::super::password;
+use super::super::password_file_path;
+use super::super::rpassword::prompt_password_stderr;
+use super::super::safe_string::SafeString;
+use super::super::safe_vec::SafeVec;
+use super::read_keyfile_from_matches;
+use std::io::Write;
+use std::iter::{Iterator, FromIterator, repeat};
+use std::path::Path;
+
+pub fn callback_help() {
+    println!("Usage:");
+    println!("    rooster backups -h");
+    println!("    rooster backups list");
+    println!("    rooster backups restore <id>");
+    println!("");
+    println!("Before your password file changes, Rooster keeps a copy of it in the .bak");
+    println!("directory next to it. The backups are encrypted, just like the password file.");
+    println!("Set $ROOSTER_BACKUPS to tell how many of them to keep, 10 by default, 0 for none.");
+    println!("");
+    println!("Restoring a backup brings its passwords and its trash back. The ways to open");
+    println!("your password file, like your master password, are left as they are. Backups");
+    println!("made before your password file was upgraded ask for the master password they");
+    println!("were made with.");
+    println!("");
+    println!("Backups are left as they were made. They still open with a master password you");
+    println!("have changed since, or with a slot you have removed, and they still hold the");
+    println!("passwords purged from the trash. To get rid of them for good, delete the .bak");
+    println!("directory.");
+    println!("");
+    println!("Example:");
+    println!("    rooster backups list");
+    println!("    rooster backups restore 2017-03-01T10-00-00");
+}
+
+fn get_backups() -> Result<Vec<Backup>, i32> {
+    let filename = match password_file_path() {
+        Ok(filename) => filename,
+        Err(_) => {
+            println_err!("Woops, I could not determine where your password file is.");
+            return Err(1);
+        }
+    };
+    match backup::list_backups(Path::new(&filename)) {
+        Ok(backups) => Ok(backups),
+        Err(err) => {
+            println_err!("Woops, I could not list your backups (reason: {}).", err);
+            Err(1)
+        }
+    }
+}
+
+fn list(store: &password::PasswordStore) -> Result<(), i32> {
+    let backups = get_backups()?;
+
+    if backups.len() == 0 {
+        println!("No backups yet. Rooster makes one every time your password file changes.");
+        return Ok(());
+    }
+
+    let horizontal_border = String::from_iter(repeat('-').take(40));
+
+    println!("{}", horizontal_border);
+    println!("| {:22} | {:9} |", "id", "passwords");
+    println!("{}", horizontal_border);
+    for backup in backups.iter() {
+        // Backups made before the password file was upgraded can't be opened with its key.
+        let count = match backup.read()
+            .map_err(password::PasswordError::from)
+            .and_then(|contents| store.read_backup(&contents)) {
+            Ok(passwords) => format!("{}", passwords.len()),
+            Err(_) => "?".to_owned(),
+        };
+        println!("| {:22} | {:>9} |", backup.id, count);
+    }
+    println!("{}", horizontal_border);
+
+    Ok(())
+}
+
+fn restore(matches: &getopts::Matches,
+           store: &mut password::PasswordStore)
+           -> Result<(), i32> {
+    let id = match matches.free.get(2) {
+        Some(id) => id,
+        None => {
+            println_err!("Woops, seems like the backup id is missing here. For help, try:");
+            println_err!("    rooster backups -h");
+            return Err(1);
+        }
+    };
+
+    let backup = match get_backups()?.into_iter().find(|backup| &backup.id == id) {
+        Some(backup) => backup,
+        None => {
+            println_err!("Woops, I couldn't find a backup with id \"{}\". To see them all, try:",
+                         id);
+            println_err!("    rooster backups list");
+            return Err(1);
+        }
+    };
+
+    let contents = match backup.read() {
+        Ok(contents) => contents,
+        Err(err) => {
+            println_err!("Woops, I could not read the backup (reason: {}).", err);
+            return Err(1);
+        }
+    };
+
+    match store.restore_backup(&contents) {
+        Ok(()) => {}
+        Err(password::PasswordError::WrongVersionError) |
+        Err(password::PasswordError::CorruptionError) => {
+            restore_old_backup(matches, store, contents)?;
+        }
+        Err(err) => {
+            println_err!("Woops, I could not open this backup (reason: {:?}).", err);
+            return Err(1);
+        }
+    }
+
+    println_ok!("Done! Your passwords are back the way they were in backup {}. The current ones \
+                 are backed up too, in case you change your mind.",
+                id);
+    Ok(())
+}
+
+/// Backups made before the password file was upgraded have another format or another key, so
+/// they are opened on their own, with the master password they were made with.
+fn restore_old_backup(matches: &getopts::Matches,
+                      store: &mut password::PasswordStore,
+                      contents: Vec<u8>)
+                      -> Result<(), i32> {
+    println_stderr!("This backup was made before your password file was upgraded, so it doesn't \
+                     open the same way.");
+    let master_password = match prompt_password_stderr("Type the master password of the \
+                                                        backup: ") {
+        Ok(master_password) => SafeString::new(master_password),
+        Err(err) => {
+            println_err!("I could not read the master password (reason: {:?}).", err);
+            return Err(1);
+        }
+    };
+    let keyfile = read_keyfile_from_matches(matches)?;
+    let allow_expensive = matches.opt_present("allow-expensive-kdf");
+    let contents = SafeVec::new(contents);
+
+    let opened = match password::PasswordStore::from_input(master_password.clone(),
+                                                           keyfile.clone(),
+                                                           contents.clone(),
+                                                           allow_expensive) {
+        // Nothing is written, so there is nothing to confirm.
+        Err(password::PasswordError::WrongVersionError) => {
+            password::upgrade(master_password, keyfile, contents, allow_expensive, |_, _| true)
+        }
+        opened => opened,
+    };
+    match opened {
+        Ok(backup) => {
+            store.restore_store(backup);
+            Ok(())
+        }
+        Err(err) => {
+            println_err!("Woops, I could not open this backup (reason: {:?}).", err);
+            Err(1)
+        }
+    }
+}
+
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::PasswordStore)
+                     -> Result<(), i32> {
+    match matches.free.get(1).map(|s| s.as_str()) {
+        None | Some("list") => list(store),
+        Some("restore") => restore(matches, store),
+        Some(subcommand) => {
+            println_err!("Woops, I don't know how to \"{}\" backups. For help, try:",
+                         subcommand);
+            println_err!("    rooster backups -h");
+            Err(1)
+        }
+    }
+}
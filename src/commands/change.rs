@@ -17,7 +17,8 @@ use super::super::password;
 use super::super::rpassword::prompt_password_stderr;
 use super::super::safe_string::SafeString;
 use super::super::clipboard::{copy_to_clipboard, paste_keys};
-use super::super::ffi;
+use super::super::timestamp;
+use super::{choose_password, fields_from_matches};
 use std::io::Write;
 use std::ops::Deref;
 
@@ -25,13 +26,54 @@ pub fn callback_help() {
     println!("Usage:");
     println!("    rooster change -h");
     println!("    rooster change <app_name>");
+    println!("    rooster change <app_name> [--field <name>=<value> ...] [--secret-field <name> \
+              ...]");
+    println!("");
+    println!("With --field or --secret-field, only the custom fields change, and the password");
+    println!("is left as it is. An empty value removes the field. Otherwise, the password");
+    println!("changes, and a pending one from `rooster regenerate --staged` is dropped.");
+    println!("");
+    println!("If you have several accounts for the app, I'll ask which one you mean. You can");
+    println!("also tell it with its username, like github:alice.");
     println!("");
     println!("Example:");
     println!("    rooster change youtube");
+    println!("    rooster change youtube --field url=https://youtube.com --field notes=");
+}
+
+fn change_fields(store: &mut password::PasswordStore,
+                 current: &password::Password,
+                 fields: Vec<password::Field>)
+                 -> Result<(), i32> {
+    let change_result = store.change_password(current.name.deref(),
+                                              current.username.deref(),
+                                              &|old_password: password::Password| {
+        let mut new_password = old_password.clone();
+        for field in fields.iter() {
+            if field.value.is_empty() {
+                new_password.remove_field(field.name.deref());
+            } else {
+                new_password.set_field(field.clone());
+            }
+        }
+        new_password.updated_at = timestamp::now();
+        new_password
+    });
+
+    match change_result {
+        Ok(_) => {
+            println_ok!("Done! I've saved the fields of \"{}\".", current.account());
+            Ok(())
+        }
+        Err(err) => {
+            println_err!("Woops, I couldn't save the fields (reason: {:?}).", err);
+            Err(1)
+        }
+    }
 }
 
 pub fn callback_exec(matches: &getopts::Matches,
-                     store: &mut password::v2::PasswordStore)
+                     store: &mut password::PasswordStore)
                      -> Result<(), i32> {
     if matches.free.len() < 2 {
         println_err!("Woops, seems like the app name is missing here. For help, try:");
@@ -41,24 +83,41 @@ pub fn callback_exec(matches: &getopts::Matches,
 
     let app_name = matches.free[1].clone();
 
+    let fields = fields_from_matches(matches)?;
+    let current = choose_password(store, app_name.deref())?;
+    if !fields.is_empty() {
+        return change_fields(store, &current, fields);
+    }
+
+    if current.is_note() {
+        println_err!("Woops, \"{}\" is a note, not a password. To change it, try:", app_name);
+        println_err!("    rooster note edit '{}'", app_name);
+        return Err(1);
+    }
+
     match prompt_password_stderr(format!("What password do you want for \"{}\"? ", app_name)
         .as_str()) {
         Ok(password_as_string) => {
             let password_as_string = SafeString::new(password_as_string.clone());
 
-            let change_result = store.change_password(app_name.deref(),
-                                                      &|old_password: password::v2::Password| {
-                password::v2::Password {
-                    name: old_password.name.clone(),
-                    username: old_password.username.clone(),
-                    password: password_as_string.clone(),
-                    created_at: old_password.created_at,
-                    updated_at: ffi::time(),
-                }
+            let change_result = store.change_password(current.name.deref(),
+                                                      current.username.deref(),
+                                                      &|old_password: password::Password| {
+                let mut new_password = old_password.clone();
+                new_password.password = password_as_string.clone();
+                new_password.updated_at = timestamp::now();
+                new_password.pending = None;
+                new_password
             });
 
             match change_result {
                 Ok(_) => {
+                    if current.pending.is_some() {
+                        println_stderr!("The password that was pending for \"{}\" has been \
+                                         dropped.",
+                                        app_name);
+                    }
+
                     if matches.opt_present("show") {
                         println_ok!("Alright! Here is your new password: {}",
                                     password_as_string.deref());
@@ -14,6 +14,9 @@
 
 use super::super::getopts;
 use super::super::password;
+use super::super::password::kdf::Kdf;
+use super::tune_kdf::parse_target;
+use super::read_keyfile_from_matches;
 use super::super::rpassword::prompt_password_stderr;
 use super::super::safe_string::SafeString;
 use std::io::Write;
@@ -22,15 +25,56 @@ use std::ops::Deref;
 pub fn callback_help() {
     println!("Usage:");
     println!("    rooster change-master -h");
-    println!("    rooster change-master");
+    println!("    rooster change-master [--keyfile <path> | --no-keyfile] [<seconds>]");
+    println!("");
+    println!("The password file is re-encrypted with a fresh salt. If <seconds> is given, the");
+    println!("key derivation parameters are also tuned so that unlocking takes about that");
+    println!("long on this computer, like `rooster tune-kdf` does.");
+    println!("");
+    println!("With --keyfile, the password file will need both the new master password and");
+    println!("the keyfile to be opened. If the password file already needs this keyfile, it");
+    println!("keeps needing it. With --no-keyfile, the keyfile will no longer be needed.");
     println!("");
     println!("Example:");
     println!("    rooster change-master");
+    println!("    rooster change-master 2");
+    println!("    rooster change-master --keyfile /media/usb/rooster.key");
 }
 
-pub fn callback_exec(_matches: &getopts::Matches,
-                     store: &mut password::v2::PasswordStore)
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::PasswordStore)
                      -> Result<(), i32> {
+    // The keyfile used to open the store is also the one the new key will need.
+    let keyfile = if matches.opt_present("no-keyfile") {
+        None
+    } else {
+        read_keyfile_from_matches(matches)?
+    };
+
+    let kdf = match matches.free.get(1) {
+        Some(seconds) => {
+            let target = match parse_target(seconds) {
+                Some(target) => target,
+                None => {
+                    println_err!("Woops, the unlock time must be a number of seconds between 0 \
+                                  and 60, for instance 1 or 2.5.");
+                    return Err(1);
+                }
+            };
+            println_stderr!("Measuring how fast your computer derives keys, this may take a \
+                             while...");
+            match Kdf::tune(target) {
+                Ok((kdf, _)) => Some(kdf),
+                Err(err) => {
+                    println_err!("Woops, I could not measure key derivation (reason: {:?}).",
+                                 err);
+                    return Err(1);
+                }
+            }
+        }
+        None => None,
+    };
+
     match prompt_password_stderr("Type your new master password: ") {
         Ok(master_password) => {
             let master_password = SafeString::new(master_password);
@@ -51,7 +95,12 @@ pub fn callback_exec(_matches: &getopts::Matches,
                 return Err(1);
             }
 
-            store.change_master_password(master_password.deref());
+            if let Err(err) = store.change_master_password(master_password.deref(),
+                                                           keyfile,
+                                                           kdf) {
+                println_err!("I could not change your master password (reason: {:?}).", err);
+                return Err(1);
+            }
         }
         Err(err) => {
             println_err!("I could not read your new master password (reason: {:?}).",
@@ -59,6 +108,5 @@ pub fn callback_exec(_matches: &getopts::Matches,
             return Err(1);
         }
     }
-    println_ok!("Your master password has been changed.");
     Ok(())
 }
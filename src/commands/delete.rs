@@ -14,19 +14,28 @@
 
 use super::super::getopts;
 use super::super::password;
+use super::choose_password;
 use std::io::Write;
+use std::ops::Deref;
 
 pub fn callback_help() {
     println!("Usage:");
     println!("    rooster delete -h");
     println!("    rooster delete <app_name> ...");
     println!("");
+    println!("Deleted passwords go to the trash, where they can be restored from. For more,");
+    println!("see `rooster trash -h`.");
+    println!("");
+    println!("If you have several accounts for an app, I'll ask which one you mean. You can");
+    println!("also tell it with its username, like github:alice.");
+    println!("");
     println!("Example:");
     println!("    rooster delete youtube");
+    println!("    rooster delete github:alice");
 }
 
 pub fn callback_exec(matches: &getopts::Matches,
-                     store: &mut password::v2::PasswordStore)
+                     store: &mut password::PasswordStore)
                      -> Result<(), i32> {
     if matches.free.len() < 2 {
         println_err!("Woops, seems like the app name is missing here. For help, try:");
@@ -37,9 +46,19 @@ pub fn callback_exec(matches: &getopts::Matches,
     let mut has_error = false;
 
     for app_name in &matches.free[1..] {
-        match store.delete_password(app_name) {
+        let password = match choose_password(store, app_name) {
+            Ok(password) => password,
+            Err(_) => {
+                has_error = true;
+                continue;
+            }
+        };
+        match store.delete_password(password.name.deref(), password.username.deref()) {
             Ok(_) => {
-                println_ok!("Done! I've deleted the password for \"{}\".", app_name);
+                println_ok!("Done! I've moved the password for \"{}\" to the trash. To bring it \
+                             back, try `rooster restore '{}'`",
+                            app_name,
+                            password.account());
             }
             Err(err) => {
                 println_err!("Woops! I couldn't find a password for \"{}\" (error: {:?}).",
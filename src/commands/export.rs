@@ -22,16 +22,20 @@ use std::io::Write;
 pub fn callback_help() {
     println!("Usage:");
     println!("    rooster export -h");
-    println!("    rooster export");
+    println!("    rooster export [<group>]");
+    println!("");
+    println!("With a group, only the passwords in it are exported.");
     println!("");
     println!("Example:");
     println!("    rooster export");
+    println!("    rooster export work/");
 }
 
-pub fn callback_exec(_matches: &getopts::Matches,
-                     store: &mut password::v2::PasswordStore)
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::PasswordStore)
                      -> Result<(), i32> {
-    let passwords_ref = store.get_all_passwords();
+    let group = matches.free.get(1).map(|s| s.as_str()).unwrap_or("");
+    let passwords_ref = store.get_passwords_in_group(group);
 
     let passwords_json = match json::encode(&passwords_ref) {
         Ok(passwords_json) => passwords_json,
@@ -15,8 +15,9 @@
 use super::super::getopts;
 use super::super::password;
 use super::super::safe_string::SafeString;
-use super::super::generate::{PasswordSpec, generate_hard_password};
+use super::super::generate::generate_hard_password;
 use super::super::clipboard::{copy_to_clipboard, paste_keys};
+use super::password_spec_from_matches;
 use std::io::Write;
 use std::ops::Deref;
 
@@ -30,7 +31,7 @@ pub fn callback_help() {
 }
 
 pub fn callback_exec(matches: &getopts::Matches,
-                     store: &mut password::v2::PasswordStore)
+                     store: &mut password::PasswordStore)
                      -> Result<(), i32> {
     if matches.free.len() < 3 {
         println_err!("Woops, seems like the app name or the username is missing here. For help, \
@@ -42,12 +43,12 @@ pub fn callback_exec(matches: &getopts::Matches,
     let app_name = matches.free[1].clone();
     let username = matches.free[2].clone();
 
-    if store.has_password(app_name.deref()) {
-        println_err!("Woops, there is already an app with that name.");
+    if store.has_account(app_name.deref(), username.deref()) {
+        println_err!("Woops, there is already an account with that app name and username.");
         return Err(1);
     }
 
-    let password_spec = PasswordSpec::from_matches(matches);
+    let password_spec = password_spec_from_matches(matches);
 
     let password_as_string = match password_spec {
         None => {
@@ -67,9 +68,9 @@ pub fn callback_exec(matches: &getopts::Matches,
 
     // Read the master password and try to save the new password.
     let password_as_string_clipboard = SafeString::new(password_as_string.clone());
-    let password = password::v2::Password::new(app_name.clone(),
-                                               username,
-                                               SafeString::new(password_as_string));
+    let password = password::Password::new(app_name.clone(),
+                                           username,
+                                           SafeString::new(password_as_string));
 
     match store.add_password(password) {
         Ok(_) => {
@@ -15,6 +15,7 @@
 use super::super::getopts;
 use super::super::password;
 use super::super::clipboard::{copy_to_clipboard, paste_keys};
+use super::choose_password;
 use std::io::Write;
 use std::ops::Deref;
 
@@ -22,14 +23,24 @@ pub fn callback_help() {
     println!("Usage:");
     println!("    rooster get -h");
     println!("    rooster get <app_name>");
+    println!("    rooster get <app_name> --field <name>");
+    println!("    rooster get <app_name> --pending");
+    println!("");
+    println!("With --field, gets the custom field with that name instead of the password.");
+    println!("With --pending, gets the new password staged by `rooster regenerate --staged`.");
+    println!("");
+    println!("If you have several accounts for the app, I'll ask which one you mean. You can");
+    println!("also tell it with its username, like github:alice.");
     println!("");
     println!("Example:");
     println!("    rooster get youtube");
     println!("    rooster get -s youtube");
+    println!("    rooster get -s youtube --field url");
+    println!("    rooster get github:alice");
 }
 
 pub fn callback_exec(matches: &getopts::Matches,
-                     store: &mut password::v2::PasswordStore)
+                     store: &mut password::PasswordStore)
                      -> Result<(), i32> {
     if matches.free.len() < 2 {
         println_err!("Woops, seems like the app name is missing here. For help, try:");
@@ -39,29 +50,94 @@ pub fn callback_exec(matches: &getopts::Matches,
 
     let app_name = &matches.free[1];
 
-    match store.get_password(app_name) {
-        Some(ref password) => {
-            if matches.opt_present("show") {
-                println_ok!("Alright! Here is your password: {}",
-                            password.password.deref());
-                return Ok(());
+    let field_names = matches.opt_strs("field");
+    if field_names.len() > 1 {
+        println_err!("Woops, I can only get one field at a time.");
+        return Err(1);
+    }
+    if !field_names.is_empty() && matches.opt_present("pending") {
+        println_err!("Woops, I can get either a field or the pending password, not both.");
+        return Err(1);
+    }
+
+    let password = choose_password(store, app_name)?;
+
+    if matches.opt_present("pending") {
+        let pending = match password.pending {
+            Some(ref pending) => pending,
+            None => {
+                println_err!("Woops, \"{}\" has no pending password. To stage one, try:",
+                             password.name);
+                println_err!("    rooster regenerate --staged '{}'", password.account());
+                return Err(1);
             }
+        };
 
-            if copy_to_clipboard(password.password.deref()).is_err() {
-                println_ok!("Hmm, I tried to copy your new password to your clipboard, but \
-                             something went wrong. You can see it with `rooster get {} --show`",
-                            app_name);
-            } else {
-                println_ok!("Alright! You can paste your password anywhere with {}.",
-                            paste_keys());
+        if matches.opt_present("show") {
+            println_ok!("Alright! Here is your pending password: {}",
+                        pending.password.deref());
+            return Ok(());
+        }
+
+        if copy_to_clipboard(pending.password.deref()).is_err() {
+            println_ok!("Hmm, I tried to copy your pending password to your clipboard, but \
+                         something went wrong. You can see it with `rooster get {} --show \
+                         --pending`",
+                        app_name);
+        } else {
+            println_ok!("Alright! You can paste your pending password anywhere with {}.",
+                        paste_keys());
+        }
+        return Ok(());
+    }
+
+    if !field_names.is_empty() {
+        let field = match password.get_field(field_names[0].deref()) {
+            Some(field) => field,
+            None => {
+                println_err!("Woops, \"{}\" has no field named \"{}\".",
+                             password.name,
+                             field_names[0]);
+                return Err(1);
             }
-            Ok(())
+        };
+
+        if matches.opt_present("show") {
+            println_ok!("Alright! Here is your {}: {}", field.name, field.value.deref());
+            return Ok(());
         }
-        None => {
-            println_err!("I couldn't find a password for this app. Make sure you");
-            println_err!("didn't make a typo. For a list of passwords, try:");
-            println_err!("    rooster list");
-            Err(1)
+
+        if copy_to_clipboard(field.value.deref()).is_err() {
+            println_ok!("Hmm, I tried to copy your {} to your clipboard, but something went \
+                         wrong. You can see it with `rooster get {} --show --field {}`",
+                        field.name,
+                        app_name,
+                        field.name);
+        } else {
+            println_ok!("Alright! You can paste your {} anywhere with {}.",
+                        field.name,
+                        paste_keys());
         }
+        return Ok(());
+    }
+
+    if password.pending.is_some() {
+        println_stderr!("\"{}\" also has a pending password, get it with --pending.",
+                        password.name);
+    }
+
+    if matches.opt_present("show") {
+        println_ok!("Alright! Here is your password: {}", password.password.deref());
+        return Ok(());
+    }
+
+    if copy_to_clipboard(password.password.deref()).is_err() {
+        println_ok!("Hmm, I tried to copy your new password to your clipboard, but something \
+                     went wrong. You can see it with `rooster get {} --show`",
+                    app_name);
+    } else {
+        println_ok!("Alright! You can paste your password anywhere with {}.",
+                    paste_keys());
     }
+    Ok(())
 }
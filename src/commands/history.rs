@@ -0,0 +1,112 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::super::getopts;
+use super::super::password;
+use super::super::timestamp;
+use super::super::clipboard::{copy_to_clipboard, paste_keys};
+use super::choose_password;
+use std::io::Write;
+use std::iter::{Iterator, FromIterator, repeat};
+use std::ops::Deref;
+
+pub fn callback_help() {
+    println!("Usage:");
+    println!("    rooster history -h");
+    println!("    rooster history <app_name>");
+    println!("    rooster history <app_name> <id>");
+    println!("");
+    println!("When a password is changed or regenerated, Rooster keeps the previous one, up to");
+    println!("{} of them. Without an id, lists them. With an id, copies that password to your",
+             password::v4::HISTORY_LEN);
+    println!("clipboard, or shows it with --show.");
+    println!("");
+    println!("Example:");
+    println!("    rooster history youtube");
+    println!("    rooster history youtube 1");
+}
+
+fn list(password: &password::Password) -> Result<(), i32> {
+    if password.history.len() == 0 {
+        println!("\"{}\" has no previous passwords yet. They are kept when it changes.",
+                 password.name);
+        return Ok(());
+    }
+
+    let horizontal_border = String::from_iter(repeat('-').take(36));
+
+    println!("{}", horizontal_border);
+    println!("| {:2} | {:27} |", "id", "replaced at");
+    println!("{}", horizontal_border);
+    for (i, previous) in password.history.iter().enumerate() {
+        println!("| {:2} | {:27} |", i + 1, timestamp::format(previous.replaced_at));
+    }
+    println!("{}", horizontal_border);
+
+    Ok(())
+}
+
+fn get(matches: &getopts::Matches, password: &password::Password, id: &str) -> Result<(), i32> {
+    let previous = match id.parse::<usize>() {
+        Ok(i) if i >= 1 && i <= password.history.len() => &password.history[i - 1],
+        _ => {
+            println_err!("Woops, \"{}\" has no previous password with id {}. To see them all, \
+                          try:",
+                         password.name,
+                         id);
+            println_err!("    rooster history '{}'", password.account());
+            return Err(1);
+        }
+    };
+
+    if matches.opt_present("show") {
+        println_ok!("Alright! Here is the password replaced at {}: {}",
+                    timestamp::format(previous.replaced_at),
+                    previous.password.deref());
+        return Ok(());
+    }
+
+    if copy_to_clipboard(previous.password.deref()).is_err() {
+        println_ok!("Hmm, I tried to copy the previous password to your clipboard, but \
+                     something went wrong. You can see it with `rooster history '{}' {} --show`",
+                    password.account(),
+                    id);
+    } else {
+        println_ok!("Alright! You can paste the previous password anywhere with {}.",
+                    paste_keys());
+    }
+    Ok(())
+}
+
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::PasswordStore)
+                     -> Result<(), i32> {
+    if matches.free.len() < 2 {
+        println_err!("Woops, seems like the app name is missing here. For help, try:");
+        println_err!("    rooster history -h");
+        return Err(1);
+    }
+
+    let password = choose_password(store, matches.free[1].deref())?;
+    if password.is_note() {
+        println_err!("Woops, \"{}\" is a note. Notes have no history, only their current text.",
+                     password.name);
+        return Err(1);
+    }
+
+    match matches.free.get(2) {
+        Some(id) => get(matches, &password, id),
+        None => list(&password),
+    }
+}
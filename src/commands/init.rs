@@ -0,0 +1,88 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::super::getopts;
+use super::super::password;
+use super::super::rpassword::prompt_password_stderr;
+use super::super::safe_string::SafeString;
+use super::{read_keyfile_from_matches, lock_password_file_from_matches};
+use std::io::Write;
+use std::path::Path;
+
+pub fn callback_help() {
+    println!("Usage:");
+    println!("    rooster init -h");
+    println!("    rooster init [--keyfile <path>]");
+    println!("");
+    println!("Creates a new password file. With --keyfile, the password file can only be");
+    println!("opened with both your master password and the keyfile. Any file can be used as");
+    println!("a keyfile, for instance:");
+    println!("    head -c 64 /dev/urandom > /media/usb/rooster.key");
+    println!("");
+    println!("Example:");
+    println!("    rooster init");
+    println!("    rooster init --keyfile /media/usb/rooster.key");
+}
+
+pub fn callback_exec(matches: &getopts::Matches, filename: &str) -> Result<(), i32> {
+    let _lock = lock_password_file_from_matches(matches, filename)?;
+
+    if Path::new(filename).exists() {
+        println_err!("Woops, there is already a password file at {}.", filename);
+        return Err(1);
+    }
+
+    let keyfile = read_keyfile_from_matches(matches)?;
+
+    let master_password = match prompt_password_stderr("Type your new master password: ") {
+        Ok(master_password) => SafeString::new(master_password),
+        Err(err) => {
+            println_err!("I could not read your new master password (reason: {:?}).", err);
+            return Err(1);
+        }
+    };
+
+    let master_password_confirmation = match prompt_password_stderr("Type your new master \
+                                                                     password once more: ") {
+        Ok(master_password_confirmation) => SafeString::new(master_password_confirmation),
+        Err(err) => {
+            println_err!("I could not read your new master password (reason: {:?}).", err);
+            return Err(1);
+        }
+    };
+
+    if master_password != master_password_confirmation {
+        println_err!("The master password confirmation did not match. Aborting.");
+        return Err(1);
+    }
+
+    let store = match password::PasswordStore::new(master_password, keyfile) {
+        Ok(store) => store,
+        Err(err) => {
+            println_err!("Woops, I could not create the password file (reason: {:?}).", err);
+            return Err(1);
+        }
+    };
+
+    match store.sync(Path::new(filename)) {
+        Ok(()) => {
+            println_ok!("Done! Your password file is ready at {}.", filename);
+            Ok(())
+        }
+        Err(err) => {
+            println_err!("I could not save the password file (reason: {:?}).", err);
+            Err(1)
+        }
+    }
+}
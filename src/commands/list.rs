@@ -14,45 +14,157 @@
 
 use super::super::getopts;
 use super::super::password;
+use std::cmp;
 use std::iter::{Iterator, FromIterator, repeat};
+use std::ops::Deref;
 use libc::isatty;
 
 pub fn callback_help() {
     println!("Usage:");
     println!("    rooster list -h");
-    println!("    rooster list");
+    println!("    rooster list [<group>] [--tree] [--tag <tag> ...]");
+    println!("");
+    println!("App names can be paths, like work/aws/prod-root, to sort passwords in groups.");
+    println!("With a group, only the passwords in it are listed. With --tree, they are shown");
+    println!("as a tree of groups. With --tag, only the passwords with all of the given tags");
+    println!("are listed.");
+    println!("");
+    println!("Secure notes are listed along with passwords, as \"(secure note)\".");
     println!("");
     println!("Example:");
     println!("    rooster list");
+    println!("    rooster list work/aws");
+    println!("    rooster list --tree");
+    println!("    rooster list --tag work");
+}
+
+fn username(p: &password::Password) -> &str {
+    if p.is_note() { "(secure note)" } else { p.username.as_str() }
+}
+
+/// A group in the tree of passwords, or the passwords at its end. There can be several, one for
+/// each account for the app.
+struct Node<'a> {
+    name: String,
+    passwords: Vec<&'a password::Password>,
+    children: Vec<Node<'a>>,
+}
+
+impl<'a> Node<'a> {
+    fn insert(&mut self, path: &[&str], password: &'a password::Password) {
+        let (first, rest) = match path.split_first() {
+            Some(split) => split,
+            None => return,
+        };
+        let i = match self.children
+            .iter()
+            .position(|c| c.name.to_lowercase() == first.to_lowercase()) {
+            Some(i) => i,
+            None => {
+                self.children.push(Node {
+                    name: first.to_string(),
+                    passwords: Vec::new(),
+                    children: Vec::new(),
+                });
+                self.children.len() - 1
+            }
+        };
+        if rest.is_empty() {
+            self.children[i].passwords.push(password);
+        } else {
+            self.children[i].insert(rest, password);
+        }
+    }
+
+    fn print(&self, prefix: &str) {
+        for (i, child) in self.children.iter().enumerate() {
+            let last = i + 1 == self.children.len();
+            if child.passwords.is_empty() {
+                println!("{}{}{}/", prefix, if last { "`-- " } else { "|-- " }, child.name);
+            }
+            // Each account gets a line of its own, the group below goes with the last one.
+            for (j, p) in child.passwords.iter().enumerate() {
+                let last_line = last && j + 1 == child.passwords.len();
+                println!("{}{}{} ({})",
+                         prefix,
+                         if last_line { "`-- " } else { "|-- " },
+                         child.name,
+                         if p.is_note() { "secure note" } else { p.username.as_str() });
+            }
+            child.print(&format!("{}{}", prefix, if last { "    " } else { "|   " }));
+        }
+    }
 }
 
-pub fn callback_exec(_matches: &getopts::Matches,
-                     store: &mut password::v2::PasswordStore)
+fn print_tree(passwords: &[&password::Password]) {
+    let mut root = Node {
+        name: String::new(),
+        passwords: Vec::new(),
+        children: Vec::new(),
+    };
+    for p in passwords.iter() {
+        let path: Vec<&str> = p.name.split('/').filter(|part| !part.is_empty()).collect();
+        root.insert(path.deref(), p);
+    }
+    root.print("");
+}
+
+fn print_table(passwords: &[&password::Password], output_is_piped: bool) {
+    // Columns are as wide as their longest value, so long paths don't break the table.
+    let app_width = passwords.iter().fold(30, |w, p| cmp::max(w, p.name.chars().count()));
+    let username_width = passwords.iter()
+        .fold(30, |w, p| cmp::max(w, username(p).chars().count()));
+
+    // We'll now print the password in a table.
+    // The table is delimited by borders.
+    let horizontal_border = String::from_iter(repeat('-').take(app_width + username_width + 13));
+
+    if !output_is_piped {
+        println!("{}", horizontal_border);
+        println!("| {:2} | {:app_width$} | {:username_width$} |",
+                 "id",
+                 "app",
+                 "username",
+                 app_width = app_width,
+                 username_width = username_width);
+        println!("{}", horizontal_border);
+    }
+    for (i, p) in passwords.iter().enumerate() {
+        println!("| {:2?} | {:app_width$} | {:username_width$} |",
+                 i,
+                 p.name,
+                 username(p),
+                 app_width = app_width,
+                 username_width = username_width);
+    }
+    if !output_is_piped {
+        println!("{}", horizontal_border);
+    }
+}
+
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::PasswordStore)
                      -> Result<(), i32> {
-    let all_passwords = store.get_all_passwords();
+    let group = matches.free.get(1).map(|s| s.as_str()).unwrap_or("");
+    let tags = matches.opt_strs("tag");
+    let mut passwords: Vec<&password::Password> = store.get_passwords_in_group(group)
+        .into_iter()
+        .filter(|p| p.has_tags(tags.deref()))
+        .collect();
 
     let output_is_piped = unsafe { isatty(1) } == 0;
 
-    if all_passwords.len() == 0 {
-        if !output_is_piped {
+    if passwords.len() == 0 {
+        if !output_is_piped && (!group.is_empty() || !tags.is_empty()) {
+            println!("No passwords here. To see them all, try 'rooster list'.");
+        } else if !output_is_piped {
             println!("No passwords on record yet. Add one with 'rooster add <app> <username>'.");
         }
+    } else if matches.opt_present("tree") {
+        passwords.sort_by_key(|p| (p.name.to_lowercase(), p.username.to_lowercase()));
+        print_tree(passwords.deref());
     } else {
-        // We'll now print the password in a table.
-        // The table is delimited by borders.
-        let horizontal_border = String::from_iter(repeat('-').take(73));
-
-        if !output_is_piped {
-            println!("{}", horizontal_border);
-            println!("| {:2} | {:30} | {:30} |", "id", "app", "username");
-            println!("{}", horizontal_border);
-        }
-        for (i, p) in all_passwords.iter().enumerate() {
-            println!("| {:2?} | {:30} | {:30} |", i, p.name, p.username);
-        }
-        if !output_is_piped {
-            println!("{}", horizontal_border);
-        }
+        print_table(passwords.deref(), output_is_piped);
     }
 
     Ok(())
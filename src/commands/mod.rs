@@ -12,6 +12,24 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::backup;
+use super::password_file_path;
+use super::generate::PasswordSpec;
+use super::getopts;
+use super::lock::{lock_password_file, LockError, PasswordFileLock};
+use super::password::{self, Field, Password, PasswordStore};
+use super::password::kdf::read_keyfile;
+use super::rpassword::prompt_password_stderr;
+use super::safe_string::SafeString;
+use super::safe_vec::SafeVec;
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{Read, Write, stdin};
+use std::mem;
+use std::ops::Deref;
+use std::path::Path;
+use std::time::Duration;
+
 pub mod get;
 pub mod add;
 pub mod delete;
@@ -23,3 +41,604 @@ pub mod change_master_password;
 pub mod rename;
 pub mod change;
 pub mod search;
+pub mod tune_kdf;
+pub mod init;
+pub mod slots;
+pub mod split_recovery;
+pub mod recover;
+pub mod backups;
+pub mod verify;
+pub mod note;
+pub mod tag;
+pub mod move_passwords;
+pub mod history;
+pub mod trash;
+pub mod restore;
+pub mod confirm;
+pub mod abort;
+
+/// A command that works on the opened password file.
+pub struct Command {
+    pub name: &'static str,
+    pub callback_exec: fn(&getopts::Matches, &mut PasswordStore) -> Result<(), i32>,
+    pub callback_help: fn(),
+}
+
+static COMMANDS: &'static [Command] =
+    &[Command {
+          name: "get",
+          callback_exec: get::callback_exec,
+          callback_help: get::callback_help,
+      },
+      Command {
+          name: "add",
+          callback_exec: add::callback_exec,
+          callback_help: add::callback_help,
+      },
+      Command {
+          name: "delete",
+          callback_exec: delete::callback_exec,
+          callback_help: delete::callback_help,
+      },
+      Command {
+          name: "generate",
+          callback_exec: generate::callback_exec,
+          callback_help: generate::callback_help,
+      },
+      Command {
+          name: "regenerate",
+          callback_exec: regenerate::callback_exec,
+          callback_help: regenerate::callback_help,
+      },
+      Command {
+          name: "list",
+          callback_exec: list::callback_exec,
+          callback_help: list::callback_help,
+      },
+      Command {
+          name: "export",
+          callback_exec: export::callback_exec,
+          callback_help: export::callback_help,
+      },
+      Command {
+          name: "change-master-password",
+          callback_exec: change_master_password::callback_exec,
+          callback_help: change_master_password::callback_help,
+      },
+      Command {
+          name: "rename",
+          callback_exec: rename::callback_exec,
+          callback_help: rename::callback_help,
+      },
+      Command {
+          name: "change",
+          callback_exec: change::callback_exec,
+          callback_help: change::callback_help,
+      },
+      Command {
+          name: "search",
+          callback_exec: search::callback_exec,
+          callback_help: search::callback_help,
+      },
+      Command {
+          name: "tune-kdf",
+          callback_exec: tune_kdf::callback_exec,
+          callback_help: tune_kdf::callback_help,
+      },
+      Command {
+          name: "slots",
+          callback_exec: slots::callback_exec,
+          callback_help: slots::callback_help,
+      },
+      Command {
+          name: "split-recovery",
+          callback_exec: split_recovery::callback_exec,
+          callback_help: split_recovery::callback_help,
+      },
+      Command {
+          name: "backups",
+          callback_exec: backups::callback_exec,
+          callback_help: backups::callback_help,
+      },
+      Command {
+          name: "note",
+          callback_exec: note::callback_exec,
+          callback_help: note::callback_help,
+      },
+      Command {
+          name: "tag",
+          callback_exec: tag::callback_exec,
+          callback_help: tag::callback_help,
+      },
+      Command {
+          name: "move",
+          callback_exec: move_passwords::callback_exec,
+          callback_help: move_passwords::callback_help,
+      },
+      Command {
+          name: "history",
+          callback_exec: history::callback_exec,
+          callback_help: history::callback_help,
+      },
+      Command {
+          name: "trash",
+          callback_exec: trash::callback_exec,
+          callback_help: trash::callback_help,
+      },
+      Command {
+          name: "restore",
+          callback_exec: restore::callback_exec,
+          callback_help: restore::callback_help,
+      },
+      Command {
+          name: "confirm",
+          callback_exec: confirm::callback_exec,
+          callback_help: confirm::callback_help,
+      },
+      Command {
+          name: "abort",
+          callback_exec: abort::callback_exec,
+          callback_help: abort::callback_help,
+      }];
+
+/// A command that works on the password file itself, instead of an opened store.
+pub struct FileCommand {
+    pub name: &'static str,
+    pub callback_exec: fn(&getopts::Matches, &str) -> Result<(), i32>,
+    pub callback_help: fn(),
+}
+
+static FILE_COMMANDS: &'static [FileCommand] =
+    &[FileCommand {
+          name: "init",
+          callback_exec: init::callback_exec,
+          callback_help: init::callback_help,
+      },
+      FileCommand {
+          name: "recover",
+          callback_exec: recover::callback_exec,
+          callback_help: recover::callback_help,
+      },
+      FileCommand {
+          name: "verify",
+          callback_exec: verify::callback_exec,
+          callback_help: verify::callback_help,
+      }];
+
+pub fn command_from_name(name: &str) -> Option<&'static Command> {
+    for c in COMMANDS.iter() {
+        if c.name == name {
+            return Some(c);
+        }
+    }
+    None
+}
+
+pub fn file_command_from_name(name: &str) -> Option<&'static FileCommand> {
+    for c in FILE_COMMANDS.iter() {
+        if c.name == name {
+            return Some(c);
+        }
+    }
+    None
+}
+
+/// Reads the kind of password to generate from the --alnum and --length options.
+pub fn password_spec_from_matches(matches: &getopts::Matches) -> Option<PasswordSpec> {
+    let alnum = matches.opt_present("alnum");
+    let mut password_len = 32;
+    if let Some(len) = matches.opt_str("length") {
+        password_len = match len.parse::<usize>() {
+            Ok(parsed_len) => {
+                // We want passwords to contain at least one uppercase letter, one lowercase
+                // letter and one digit. So we need at least 4 characters for each password.
+                // This checks makes sure we don't run into an infinite loop trying to generate
+                // a password of length <4 with 4 different kinds of characters (uppercase,
+                // lowercase, numeric, punctuation).
+                if parsed_len < 4 {
+                    println_err!("Woops! The length of the password must be at least 4. This");
+                    println_err!("allows us to make sure your password is secure.");
+                    return None;
+                }
+                parsed_len
+            }
+            Err(_) => {
+                println_err!("Woops! The length option must be a valid number, for instance 8 \
+                              or 16.");
+                return None;
+            }
+        }
+    }
+    Some(PasswordSpec {
+        alnum: alnum,
+        len: password_len,
+    })
+}
+
+/// Finds the password for an app name, or for "app:username". If there are several accounts
+/// for the app, asks which one to use.
+pub fn choose_password(store: &PasswordStore, name: &str) -> Result<Password, i32> {
+    let accounts = store.get_accounts(name);
+    match accounts.len() {
+        0 => {
+            println_err!("I couldn't find a password for this app. Make sure you");
+            println_err!("didn't make a typo. For a list of passwords, try:");
+            println_err!("    rooster list");
+            return Err(1);
+        }
+        1 => return Ok(accounts[0].clone()),
+        _ => {}
+    }
+
+    // App names and usernames are shown apart, since "app:username" can read the same for two
+    // accounts when they have colons.
+    println_stderr!("There are several accounts for \"{}\":", name);
+    let name_width = accounts.iter().map(|p| p.name.chars().count()).max().unwrap_or(0);
+    for (i, p) in accounts.iter().enumerate() {
+        println_stderr!("{:3} {:width$}  {}", i + 1, p.name, p.username, width = name_width);
+    }
+    println_stderr!("Which one do you mean? ");
+
+    let mut line = String::new();
+    loop {
+        line.clear();
+        match stdin().read_line(&mut line) {
+            Ok(0) => {
+                println_err!("Woops, I didn't get an answer. Tell me which account you mean \
+                              with its username, for instance \"{}\".",
+                             accounts[0].account());
+                return Err(1);
+            }
+            Ok(_) => {}
+            Err(err) => {
+                println_err!("I couldn't read that (reason: {}).", err);
+                return Err(1);
+            }
+        }
+
+        match line.trim().parse::<usize>() {
+            Ok(index) if index >= 1 && index <= accounts.len() => {
+                return Ok(accounts[index - 1].clone());
+            }
+            _ => {
+                println_err!("Sorry, I need a number between 1 and {}. Let's try this again:",
+                             accounts.len());
+            }
+        }
+    }
+}
+
+/// Reads the custom fields given with the --field NAME=VALUE and --secret-field NAME options.
+/// The values of secret fields are asked for, so they don't end up in the shell history.
+///
+/// A field with an empty value is one to remove.
+pub fn fields_from_matches(matches: &getopts::Matches) -> Result<Vec<Field>, i32> {
+    let mut fields = Vec::new();
+    for field in matches.opt_strs("field") {
+        let mut parts = field.splitn(2, '=');
+        let name = parts.next().unwrap_or("").trim().to_owned();
+        let value = match parts.next() {
+            Some(value) => value.to_owned(),
+            None => {
+                println_err!("Woops, --field takes a name and a value, for instance \
+                              --field url=https://example.com.");
+                return Err(1);
+            }
+        };
+        fields.push(Field {
+            name: name,
+            value: SafeString::new(value),
+            secret: false,
+        });
+    }
+    for name in matches.opt_strs("secret-field") {
+        let name = name.trim().to_owned();
+        let value = match prompt_password_stderr(format!("What value do you want for \"{}\"? ",
+                                                         name)
+            .as_str()) {
+            Ok(value) => value,
+            Err(err) => {
+                println_err!("Woops, I could not read the value (reason: {}).", err);
+                return Err(1);
+            }
+        };
+        fields.push(Field {
+            name: name,
+            value: SafeString::new(value),
+            secret: true,
+        });
+    }
+    for (i, field) in fields.iter().enumerate() {
+        if field.name.is_empty() {
+            println_err!("Woops, custom fields need a name.");
+            return Err(1);
+        }
+        if fields[..i].iter().any(|f| f.name.to_lowercase() == field.name.to_lowercase()) {
+            println_err!("Woops, the field \"{}\" is given more than once.", field.name);
+            return Err(1);
+        }
+    }
+    Ok(fields)
+}
+
+/// Reads the keyfile given with the --keyfile option, if any.
+pub fn read_keyfile_from_matches(matches: &getopts::Matches) -> Result<Option<SafeVec>, i32> {
+    match matches.opt_str("keyfile") {
+        Some(path) => {
+            match read_keyfile(Path::new(&path)) {
+                Ok(keyfile) => Ok(Some(keyfile)),
+                Err(err) => {
+                    println_err!("Woops, I could not read your keyfile at {} (reason: {}).",
+                                 path,
+                                 err);
+                    Err(1)
+                }
+            }
+        }
+        None => Ok(None),
+    }
+}
+
+/// Locks the password file, waiting for other Rooster processes as long as the --wait option
+/// says. Without it, we don't wait at all.
+pub fn lock_password_file_from_matches(matches: &getopts::Matches,
+                                       filename: &str)
+                                       -> Result<PasswordFileLock, i32> {
+    let wait = match matches.opt_str("wait") {
+        Some(seconds) => {
+            match seconds.parse::<u64>() {
+                Ok(seconds) => Duration::from_secs(seconds),
+                Err(_) => {
+                    println_err!("Woops, --wait takes a number of seconds, for instance 10.");
+                    return Err(1);
+                }
+            }
+        }
+        None => Duration::from_secs(0),
+    };
+
+    match lock_password_file(Path::new(filename), wait) {
+        Ok(lock) => Ok(lock),
+        Err(LockError::Busy) => {
+            println_err!("Woops, your password file is busy, another Rooster is using it. Try \
+                          again in a moment, or wait for it with --wait <seconds>.");
+            Err(1)
+        }
+        Err(LockError::Io(err)) => {
+            println_err!("Woops, I could not lock your password file (reason: {}).", err);
+            Err(1)
+        }
+    }
+}
+
+/// Keeps the current contents of the password file as a backup, before it is replaced.
+pub fn backup_password_file(filename: &str, contents: &[u8]) -> Result<(), i32> {
+    let retention = match backup::get_retention() {
+        Some(retention) => retention,
+        None => {
+            println_err!("Woops, ${} must be the number of backups to keep, for instance 10.",
+                         backup::RETENTION_ENV_VAR);
+            return Err(1);
+        }
+    };
+
+    match backup::create_backup(Path::new(filename), contents, retention) {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            println_err!("Woops, I could not back up your password file (reason: {}). I've left \
+                          it as it was.",
+                         err);
+            Err(1)
+        }
+    }
+}
+
+/// Backups are copies of the password file as it was, so what was just revoked or purged from
+/// it is still in them. This tells where they are, for those who want it gone for good.
+pub fn warn_about_backups(what_they_still_do: &str) {
+    let filename = match password_file_path() {
+        Ok(filename) => filename,
+        Err(_) => return,
+    };
+    let has_backups = backup::list_backups(Path::new(&filename))
+        .map(|backups| !backups.is_empty())
+        .unwrap_or(false);
+    if !has_backups {
+        return;
+    }
+    if let Ok(directory) = backup::backup_directory(Path::new(&filename)) {
+        println_stderr!("Your backups still {}. To get rid of them, delete {}",
+                        what_they_still_do,
+                        directory.to_string_lossy());
+    }
+}
+
+/// Secrets made up by a command, like a recovery key, and what to tell before showing them.
+struct SecretsToShow {
+    intro: String,
+    secrets: Vec<SafeString>,
+}
+
+thread_local! {
+    static SECRETS_TO_SHOW: RefCell<Vec<SecretsToShow>> = RefCell::new(Vec::new());
+}
+
+/// Shows secrets once the password file is saved. They only open the file as it was saved, so
+/// if saving fails, they are never shown.
+///
+/// The intro goes to stderr, then each secret on a line of its own on stdout.
+pub fn show_once_saved(intro: &str, secrets: Vec<SafeString>) {
+    SECRETS_TO_SHOW.with(|to_show| {
+        to_show.borrow_mut().push(SecretsToShow {
+            intro: intro.to_owned(),
+            secrets: secrets,
+        })
+    });
+}
+
+fn take_secrets_to_show() -> Vec<SecretsToShow> {
+    SECRETS_TO_SHOW.with(|to_show| mem::replace(&mut *to_show.borrow_mut(), Vec::new()))
+}
+
+/// Asks whether to upgrade the password file from `old_version` to `new_version`, with a
+/// summary of what will happen.
+fn confirm_upgrade(old_version: u32, new_version: u32) -> bool {
+    let steps: Vec<String> = (old_version..new_version + 1).map(|v| format!("v{}", v)).collect();
+
+    println_stderr!("Your Rooster file has version {}. You need to upgrade to version {} ({}).",
+                    old_version,
+                    new_version,
+                    steps.join(" -> "));
+    match backup::get_retention() {
+        Some(0) => {
+            println_stderr!("Backups are turned off, so the file will not be kept as it is now.");
+        }
+        _ => {
+            println_stderr!("The file will be kept as it is now in your backups, see `rooster \
+                             backups`.");
+        }
+    }
+    println_stderr!("");
+    println_stderr!("WARNING: If in doubt, it could mean you've been hacked. Only");
+    println_stderr!("proceed if you recently upgraded your Rooster installation.");
+    println_stderr!("");
+    println_stderr!("Upgrade to version {}? [y/n]", new_version);
+    loop {
+        let mut line = String::new();
+        match std::io::stdin().read_line(&mut line) {
+            // A closed stdin will never say yes.
+            Ok(0) => return false,
+            Ok(_) => {
+                if line.starts_with('y') {
+                    return true;
+                } else if line.starts_with('n') {
+                    return false;
+                } else {
+                    println_stderr!("I did not get that. Upgrade from v{} to v{}? [y/n]",
+                                    old_version,
+                                    new_version);
+                }
+            }
+            Err(_) => return false,
+        }
+    }
+}
+
+/// Opens the password file, runs the command on it and saves it if the command changed it.
+///
+/// The file is locked all along, upgraded first if it has an older format, and backed up before
+/// it is replaced.
+pub fn execute_command_from_filename(matches: &getopts::Matches,
+                                     command: &Command,
+                                     filename: &str,
+                                     master_password: SafeString,
+                                     keyfile: Option<SafeVec>)
+                                     -> Result<(), i32> {
+    // Hold the lock from reading the password file to writing it back. Otherwise, another
+    // Rooster could save its changes in between, and we would overwrite them.
+    let _lock = lock_password_file_from_matches(matches, filename)?;
+
+    // Open the file once we have the lock, since saving replaces it with a new one.
+    let mut input: Vec<u8> = Vec::new();
+    File::open(filename).and_then(|mut file| file.read_to_end(&mut input)).map_err(|_| 1)?;
+
+    // If the password file is empty (ie new), we'll make a new, empty store.
+    let mut store = if input.is_empty() {
+        password::PasswordStore::new(master_password.clone(), keyfile).map_err(|_| 1)?
+    } else {
+        // Try to open the file as is. If we can't, we may need to upgrade its format first.
+        let allow_expensive = matches.opt_present("allow-expensive-kdf");
+        let opened = match password::PasswordStore::from_input(master_password.clone(),
+                                                               keyfile.clone(),
+                                                               SafeVec::new(input.clone()),
+                                                               allow_expensive) {
+            Err(password::PasswordError::WrongVersionError) => {
+                password::upgrade(master_password.clone(),
+                                  keyfile,
+                                  SafeVec::new(input.clone()),
+                                  allow_expensive,
+                                  confirm_upgrade)
+            }
+            opened => opened,
+        };
+        match opened {
+            Ok(store) => store,
+            Err(password::PasswordError::KeyfileRequiredError) => {
+                println_err!("This password file can only be opened with its keyfile. Tell me \
+                              where it is with:");
+                println_err!("    rooster --keyfile <path> {}", command.name);
+                return Err(1);
+            }
+            Err(password::PasswordError::ExpensiveKdfError) => {
+                println_err!("This password file asks for a key derivation that would take more \
+                              memory or time than I'm willing to spend. If it wasn't tampered \
+                              with, open it anyway with:");
+                println_err!("    rooster --allow-expensive-kdf {}", command.name);
+                return Err(1);
+            }
+            Err(_) => {
+                // If we can't upgrade its format either, we show a helpful
+                // error message.
+                println_err!("I could not upgrade the Rooster file. This \
+                              could be because:");
+                println_err!("- you explicitly told Rooster not to open the \
+                              file,");
+                println_err!("- your version of Rooster is outdated,");
+                println_err!("- your Rooster file is corrupted,");
+                println_err!("- your master password or your keyfile is wrong.");
+                println_err!("Try upgrading to the latest version of Rooster.");
+                return Err(1);
+            }
+        }
+    };
+
+    // Execute the command and save the new password list
+    let result = (command.callback_exec)(matches, &mut store);
+    let secrets_to_show = take_secrets_to_show();
+    result?;
+
+    if !store.is_modified() {
+        return Ok(());
+    }
+
+    // Only purge the trash when the file changes anyway, so reading it never writes it.
+    trash::purge_expired(&mut store)?;
+
+    // Keep the previous version around, in case the command was a mistake.
+    if !input.is_empty() {
+        backup_password_file(filename, input.deref())?;
+    }
+
+    match store.sync(Path::new(filename)) {
+        Ok(()) => {
+            // Only now is the new master password known to open the saved file.
+            if store.is_master_password_changed() {
+                println_ok!("Your master password has been changed.");
+                warn_about_backups("open with your old master password");
+            }
+            for to_show in secrets_to_show.iter() {
+                println_stderr!("{}", to_show.intro);
+                for secret in to_show.secrets.iter() {
+                    println!("{}", secret.deref());
+                }
+            }
+            Ok(())
+        }
+        Err(password::PasswordError::CorruptionError) if store.is_master_password_changed() => {
+            println_err!("Woops, I saved the password file, but it doesn't open with your new \
+                          master password.");
+            println_err!("The previous version is the latest backup in {}, it still opens with \
+                          your old master password. Copy it over the password file to bring it \
+                          back.",
+                         backup::backup_directory(Path::new(filename))
+                             .map(|directory| directory.to_string_lossy().into_owned())
+                             .unwrap_or_default());
+            Err(1)
+        }
+        Err(err) => {
+            println_err!("I could not save the password file (reason: {:?}).", err);
+            println_err!("Don't worry, your password file was left as it was.");
+            Err(1)
+        }
+    }
+}
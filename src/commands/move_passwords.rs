@@ -0,0 +1,72 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::super::getopts;
+use super::super::password;
+use std::io::Write;
+
+pub fn callback_help() {
+    println!("Usage:");
+    println!("    rooster move -h");
+    println!("    rooster move <app_name> <new_app_name>");
+    println!("    rooster move <group> <new_group>");
+    println!("");
+    println!("App names can be paths, like work/aws/prod-root. Moving a group moves all the");
+    println!("passwords in it at once. Nothing moves if one of the new app names is taken.");
+    println!("");
+    println!("Example:");
+    println!("    rooster move work/github personal/github");
+    println!("    rooster move work/aws clients/acme/aws");
+}
+
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::PasswordStore)
+                     -> Result<(), i32> {
+    let (from, to) = match (matches.free.get(1), matches.free.get(2)) {
+        (Some(from), Some(to)) => (from, to),
+        _ => {
+            println_err!("Woops, seems like what to move or where to is missing here. For help, \
+                          try:");
+            println_err!("    rooster move -h");
+            return Err(1);
+        }
+    };
+
+    match store.move_passwords(from, to) {
+        Ok(1) => {
+            println_ok!("Done! I've moved 1 password from {} to {}.", from, to);
+            Ok(())
+        }
+        Ok(count) => {
+            println_ok!("Done! I've moved {} passwords from {} to {}.", count, from, to);
+            Ok(())
+        }
+        Err(password::PasswordError::NoSuchAppError) => {
+            println_err!("Woops, there is no app or group named \"{}\". For a list of \
+                          passwords, try:",
+                         from);
+            println_err!("    rooster list --tree");
+            Err(1)
+        }
+        Err(password::PasswordError::AppExistsError) => {
+            println_err!("Woops, some of the passwords would take the name of another one, so \
+                          I've left them where they were.");
+            Err(1)
+        }
+        Err(err) => {
+            println_err!("Woops, I couldn't move the passwords (reason: {:?}).", err);
+            Err(1)
+        }
+    }
+}
@@ -0,0 +1,249 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::super::getopts;
+use super::super::password;
+use super::super::safe_string::SafeString;
+use super::super::safe_vec::SafeVec;
+use super::super::timestamp;
+use super::choose_password;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{stdin, Read, Write, Seek, SeekFrom, Result as IoResult, Error as IoError,
+              ErrorKind as IoErrorKind};
+use std::ops::Deref;
+use std::path::Path;
+use std::process::{self, Command};
+
+pub fn callback_help() {
+    println!("Usage:");
+    println!("    rooster note -h");
+    println!("    rooster note add <name>");
+    println!("    rooster note edit <name>");
+    println!("    rooster note show <name>");
+    println!("");
+    println!("Secure notes keep secrets that aren't a username and a password, like recovery");
+    println!("instructions or license keys. They are encrypted along with your passwords.");
+    println!("");
+    println!("The text of the note is written in $EDITOR. Without it, you type it in the");
+    println!("terminal. Editing a note replaces its text, the earlier one isn't kept.");
+    println!("");
+    println!("Example:");
+    println!("    rooster note add \"Server recovery\"");
+    println!("    rooster note show \"Server recovery\"");
+}
+
+/// Creates the temporary file the note is edited in. Only its owner can read it, and it must
+/// not exist yet, so nobody can have it opened already.
+#[cfg(unix)]
+fn create_temporary_file(path: &Path) -> IoResult<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    OpenOptions::new().read(true).write(true).create_new(true).mode(0o600).open(path)
+}
+
+#[cfg(not(unix))]
+fn create_temporary_file(path: &Path) -> IoResult<File> {
+    OpenOptions::new().read(true).write(true).create_new(true).open(path)
+}
+
+/// Opens the text in the editor, and reads it back once the editor is closed.
+///
+/// The text goes through a temporary file, which is overwritten before it is removed.
+fn read_from_editor(editor: &str, text: &str) -> IoResult<SafeString> {
+    let path = env::temp_dir().join(format!("rooster-note-{}.txt", process::id()));
+    let mut file = create_temporary_file(&path)?;
+
+    let result = file.write_all(text.as_bytes())
+        .and_then(|_| file.sync_all())
+        .and_then(|_| {
+            // $EDITOR may come with arguments, like "code --wait".
+            let mut words = editor.split_whitespace();
+            let program = words.next().unwrap_or(editor);
+            Command::new(program).args(words).arg(&path).status()
+        })
+        .and_then(|status| if status.success() {
+            Ok(())
+        } else {
+            Err(IoError::new(IoErrorKind::Other, format!("{} exited with {}", editor, status)))
+        })
+        .and_then(|_| {
+            // Editors often write a new file in place of the old one, so we open it again.
+            let mut contents = Vec::new();
+            File::open(&path)?.read_to_end(&mut contents)?;
+            Ok(SafeVec::new(contents))
+        });
+
+    // Wipe the file whatever happened, it may hold the note.
+    let wiped = fs::metadata(&path).and_then(|metadata| {
+        let mut file = OpenOptions::new().write(true).open(&path)?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&vec![0u8; metadata.len() as usize])?;
+        file.sync_all()
+    });
+    let removed = fs::remove_file(&path);
+
+    let contents = result?;
+    wiped?;
+    removed?;
+    match String::from_utf8(contents.deref().to_owned()) {
+        Ok(text) => Ok(SafeString::new(text)),
+        Err(err) => {
+            drop(SafeVec::new(err.into_bytes()));
+            Err(IoError::new(IoErrorKind::InvalidData, "the note is not valid UTF-8"))
+        }
+    }
+}
+
+fn read_from_stdin() -> IoResult<SafeString> {
+    let mut text = String::new();
+    match stdin().read_to_string(&mut text) {
+        Ok(_) => Ok(SafeString::new(text)),
+        Err(err) => {
+            drop(SafeString::new(text));
+            Err(err)
+        }
+    }
+}
+
+/// Reads the text of a note, starting from `current` in the editor.
+fn read_note(current: &str) -> Result<SafeString, i32> {
+    let result = match env::var("EDITOR") {
+        Ok(ref editor) if !editor.trim().is_empty() => {
+            if current.is_empty() {
+                read_from_editor(editor.trim(), "")
+            } else {
+                read_from_editor(editor.trim(), SafeString::new(format!("{}\n", current)).deref())
+            }
+        }
+        _ => {
+            println_stderr!("Type the text of the note, then press Ctrl-D on an empty line:");
+            read_from_stdin()
+        }
+    };
+
+    let text = match result {
+        Ok(text) => text,
+        Err(err) => {
+            println_err!("Woops, I could not read the note (reason: {}).", err);
+            return Err(1);
+        }
+    };
+
+    // Editors end files with a new line, which isn't part of the note.
+    let text = SafeString::new(text.trim_end().to_owned());
+    if text.is_empty() {
+        println_err!("Woops, the note is empty. I've left it as it was.");
+        return Err(1);
+    }
+    Ok(text)
+}
+
+fn get_note(matches: &getopts::Matches,
+            store: &password::PasswordStore)
+            -> Result<password::Password, i32> {
+    let name = name_from_matches(matches)?;
+    let note = choose_password(store, name)?;
+    if !note.is_note() {
+        println_err!("Woops, \"{}\" is a password, not a note. To get it, try:", note.name);
+        println_err!("    rooster get '{}'", note.account());
+        return Err(1);
+    }
+    Ok(note)
+}
+
+fn name_from_matches(matches: &getopts::Matches) -> Result<&str, i32> {
+    match matches.free.get(2) {
+        Some(name) => Ok(name.as_str()),
+        None => {
+            println_err!("Woops, seems like the name of the note is missing here. For help, \
+                          try:");
+            println_err!("    rooster note -h");
+            Err(1)
+        }
+    }
+}
+
+fn add(matches: &getopts::Matches, store: &mut password::PasswordStore) -> Result<(), i32> {
+    let name = name_from_matches(matches)?;
+    if store.has_account(name, "") {
+        println_err!("Woops, there is already a note with that name.");
+        return Err(1);
+    }
+
+    let text = read_note("")?;
+    match store.add_password(password::Password::new_note(name.to_owned(), text)) {
+        Ok(()) => {
+            println_ok!("Alright! I've saved your note. You can see it with `rooster note show \
+                         '{}'`",
+                        name);
+            Ok(())
+        }
+        Err(err) => {
+            println_err!("Woops, I couldn't add the note (reason: {:?}).", err);
+            Err(1)
+        }
+    }
+}
+
+fn edit(matches: &getopts::Matches, store: &mut password::PasswordStore) -> Result<(), i32> {
+    let note = get_note(matches, store)?;
+    let text = read_note(note.password.deref())?;
+
+    let change_result = store.change_password(note.name.deref(),
+                                              note.username.deref(),
+                                              &|old_note: password::Password| {
+        let mut new_note = old_note.clone();
+        new_note.password = text.clone();
+        new_note.updated_at = timestamp::now();
+        new_note
+    });
+
+    match change_result {
+        Ok(()) => {
+            println_ok!("Done! I've saved your note \"{}\".", note.name);
+            Ok(())
+        }
+        Err(err) => {
+            println_err!("Woops, I couldn't save the note (reason: {:?}).", err);
+            Err(1)
+        }
+    }
+}
+
+fn show(matches: &getopts::Matches, store: &password::PasswordStore) -> Result<(), i32> {
+    let note = get_note(matches, store)?;
+    println!("{}", note.password.deref());
+    Ok(())
+}
+
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::PasswordStore)
+                     -> Result<(), i32> {
+    match matches.free.get(1).map(|s| s.as_str()) {
+        Some("add") => add(matches, store),
+        Some("edit") => edit(matches, store),
+        Some("show") => show(matches, store),
+        Some(subcommand) => {
+            println_err!("Woops, I don't know how to \"{}\" notes. For help, try:", subcommand);
+            println_err!("    rooster note -h");
+            Err(1)
+        }
+        None => {
+            println_err!("Woops, seems like the subcommand is missing here. For help, try:");
+            println_err!("    rooster note -h");
+            Err(1)
+        }
+    }
+}
@@ -0,0 +1,165 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::super::getopts;
+use super::super::password;
+use super::super::password::slot::{parse_recovery_key_share, format_recovery_key};
+use super::super::rpassword::prompt_password_stderr;
+use super::super::safe_string::SafeString;
+use super::super::safe_vec::SafeVec;
+use super::super::shamir::{self, Share};
+use super::{read_keyfile_from_matches, lock_password_file_from_matches,
+            backup_password_file};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::ops::Deref;
+use std::path::Path;
+
+pub fn callback_help() {
+    println!("Usage:");
+    println!("    rooster recover -h");
+    println!("    rooster recover [--keyfile <path>]");
+    println!("");
+    println!("Rebuilds a recovery key from the shares made by `rooster split-recovery`, and");
+    println!("uses it to set a new master password. You will be asked for shares until there");
+    println!("are enough of them. With --keyfile, the new master password will also need the");
+    println!("keyfile.");
+    println!("");
+    println!("Example:");
+    println!("    rooster recover");
+}
+
+fn read_share(prompt: &str) -> Result<Share, i32> {
+    let share = match prompt_password_stderr(prompt) {
+        Ok(share) => SafeString::new(share),
+        Err(err) => {
+            println_err!("I could not read the share (reason: {:?}).", err);
+            return Err(1);
+        }
+    };
+    match parse_recovery_key_share(share.deref()) {
+        Some(share) => Ok(share),
+        None => {
+            println_err!("Woops, that doesn't look like a share of a recovery key.");
+            Err(1)
+        }
+    }
+}
+
+pub fn callback_exec(matches: &getopts::Matches, filename: &str) -> Result<(), i32> {
+    let _lock = lock_password_file_from_matches(matches, filename)?;
+
+    let mut file = match File::open(filename) {
+        Ok(file) => file,
+        Err(err) => {
+            println_err!("Woops, I could not open the password file at {} (reason: {}).",
+                         filename,
+                         err);
+            return Err(1);
+        }
+    };
+
+    let mut input: Vec<u8> = Vec::new();
+    if let Err(err) = file.read_to_end(&mut input) {
+        println_err!("Woops, I could not read the password file (reason: {}).", err);
+        return Err(1);
+    }
+    let input = SafeVec::new(input);
+
+    // The keyfile is for the new master password, so make sure it's there before going on.
+    let keyfile = read_keyfile_from_matches(matches)?;
+
+    // The first share tells how many we need.
+    let mut shares = vec![read_share("Type a share of the recovery key: ")?];
+    let threshold = shares[0].threshold;
+    while shares.len() < threshold as usize {
+        let share = read_share(format!("Type share {} of {}: ", shares.len() + 1, threshold)
+            .deref())?;
+        if share.threshold != threshold {
+            println_err!("Woops, this share doesn't come from the same recovery key as the \
+                          first one.");
+            return Err(1);
+        }
+        if shares.iter().any(|s| s.x == share.x) {
+            println_err!("Woops, you already typed this share. I need {} different ones.",
+                         threshold);
+            return Err(1);
+        }
+        shares.push(share);
+    }
+
+    let recovery_key = match shamir::combine(shares.deref()) {
+        Some(recovery_key) => format_recovery_key(recovery_key.deref()),
+        None => {
+            println_err!("Woops, these shares don't fit together.");
+            return Err(1);
+        }
+    };
+
+    let allow_expensive = matches.opt_present("allow-expensive-kdf");
+    let mut store = match password::PasswordStore::from_input(recovery_key,
+                                                              None,
+                                                              input.clone(),
+                                                              allow_expensive) {
+        Ok(store) => store,
+        Err(password::PasswordError::DecryptionError) => {
+            println_err!("Woops, these shares don't open your password file.");
+            return Err(1);
+        }
+        Err(err) => {
+            println_err!("Woops, I could not open your password file (reason: {:?}).", err);
+            return Err(1);
+        }
+    };
+
+    let master_password = match prompt_password_stderr("Type your new master password: ") {
+        Ok(master_password) => SafeString::new(master_password),
+        Err(err) => {
+            println_err!("I could not read your new master password (reason: {:?}).", err);
+            return Err(1);
+        }
+    };
+
+    let master_password_confirmation = match prompt_password_stderr("Type your new master \
+                                                                     password once more: ") {
+        Ok(master_password_confirmation) => SafeString::new(master_password_confirmation),
+        Err(err) => {
+            println_err!("I could not read your new master password (reason: {:?}).", err);
+            return Err(1);
+        }
+    };
+
+    if master_password != master_password_confirmation {
+        println_err!("The master password confirmation did not match. Aborting.");
+        return Err(1);
+    }
+
+    if let Err(err) = store.change_master_password(master_password.deref(), keyfile, None) {
+        println_err!("I could not change your master password (reason: {:?}).", err);
+        return Err(1);
+    }
+
+    backup_password_file(filename, input.deref())?;
+
+    match store.sync(Path::new(filename)) {
+        Ok(()) => {
+            println_ok!("Done! Your password file now opens with your new master password.");
+            Ok(())
+        }
+        Err(err) => {
+            println_err!("I could not save the password file (reason: {:?}).", err);
+            Err(1)
+        }
+    }
+}
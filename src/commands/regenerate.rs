@@ -14,10 +14,11 @@
 
 use super::super::getopts;
 use super::super::safe_string::SafeString;
-use super::super::ffi;
+use super::super::timestamp;
 use super::super::password;
-use super::super::generate::{PasswordSpec, generate_hard_password};
+use super::super::generate::generate_hard_password;
 use super::super::clipboard::{copy_to_clipboard, paste_keys};
+use super::{choose_password, password_spec_from_matches};
 use std::io::Write;
 use std::ops::Deref;
 
@@ -25,13 +26,23 @@ pub fn callback_help() {
     println!("Usage:");
     println!("    rooster regenerate -h");
     println!("    rooster regenerate <app_name>");
+    println!("    rooster regenerate --staged <app_name>");
+    println!("");
+    println!("With --staged, the new password is kept as pending, next to the current one, until");
+    println!("the app has accepted it. Get it with `rooster get --pending <app_name>`, then");
+    println!("either use it from now on with `rooster confirm <app_name>`, or drop it with");
+    println!("`rooster abort <app_name>`. Without --staged, a pending password is dropped.");
+    println!("");
+    println!("If you have several accounts for the app, I'll ask which one you mean. You can");
+    println!("also tell it with its username, like github:alice.");
     println!("");
     println!("Example:");
     println!("    rooster regenerate youtube");
+    println!("    rooster regenerate --staged youtube");
 }
 
 pub fn callback_exec(matches: &getopts::Matches,
-                     store: &mut password::v2::PasswordStore)
+                     store: &mut password::PasswordStore)
                      -> Result<(), i32> {
     if matches.free.len() < 2 {
         println_err!("Woops, seems like the app name is missing here. For help, try:");
@@ -41,7 +52,14 @@ pub fn callback_exec(matches: &getopts::Matches,
 
     let app_name = matches.free[1].clone();
 
-    let password_spec = PasswordSpec::from_matches(matches);
+    let current = choose_password(store, app_name.deref())?;
+    if current.is_note() {
+        println_err!("Woops, \"{}\" is a note, not a password. To change it, try:", app_name);
+        println_err!("    rooster note edit '{}'", app_name);
+        return Err(1);
+    }
+
+    let password_spec = password_spec_from_matches(matches);
 
     let password_as_string = match password_spec {
         None => {
@@ -59,19 +77,60 @@ pub fn callback_exec(matches: &getopts::Matches,
         }
     };
 
-    let change_result = store.change_password(app_name.deref(),
-                                              &|old_password: password::v2::Password| {
-        password::v2::Password {
-            name: old_password.name.clone(),
-            username: old_password.username.clone(),
-            password: SafeString::new(password_as_string.clone()),
-            created_at: old_password.created_at,
-            updated_at: ffi::time(),
+    let staged = matches.opt_present("staged");
+
+    let change_result = store.change_password(current.name.deref(),
+                                              current.username.deref(),
+                                              &|old_password: password::Password| {
+        if staged {
+            let mut new_password = old_password.clone();
+            new_password.pending = Some(password::PendingPassword {
+                password: SafeString::new(password_as_string.clone()),
+                staged_at: timestamp::now(),
+            });
+            return new_password;
         }
+
+        let mut new_password = old_password.clone();
+        new_password.password = SafeString::new(password_as_string.clone());
+        new_password.updated_at = timestamp::now();
+        new_password.pending = None;
+        new_password
     });
 
     match change_result {
+        Ok(_) if staged => {
+            if current.pending.is_some() {
+                println_stderr!("The password that was pending for \"{}\" has been replaced.",
+                                app_name);
+            }
+
+            if matches.opt_present("show") {
+                println_ok!("Alright! Here is your pending password: {}",
+                            password_as_string.deref());
+            } else if copy_to_clipboard(password_as_string.deref()).is_err() {
+                println_ok!("Hmm, I tried to copy your pending password to your clipboard, but \
+                             something went wrong. Don't worry, it's saved, and you can see it \
+                             with `rooster get {} --show --pending`",
+                            app_name);
+            } else {
+                println_ok!("Done! I've saved your pending password for \"{}\". You can paste \
+                             it anywhere with {}.",
+                            app_name,
+                            paste_keys());
+            }
+            println_ok!("The current password still works. Once the app has accepted the new \
+                         one, run `rooster confirm {}`, or `rooster abort {}` if it didn't.",
+                        app_name,
+                        app_name);
+            Ok(())
+        }
         Ok(_) => {
+            if current.pending.is_some() {
+                println_stderr!("The password that was pending for \"{}\" has been dropped.",
+                                app_name);
+            }
+
             if matches.opt_present("show") {
                 println_ok!("Alright! Here is your new password: {}",
                             password_as_string.deref());
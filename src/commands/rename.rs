@@ -14,7 +14,8 @@
 
 use super::super::getopts;
 use super::super::password;
-use super::super::ffi;
+use super::super::timestamp;
+use super::choose_password;
 use std::io::Write;
 use std::ops::Deref;
 
@@ -23,12 +24,15 @@ pub fn callback_help() {
     println!("    rooster rename -h");
     println!("    rooster rename <old_app_name> <new_app_name>");
     println!("");
+    println!("If you have several accounts for the app, I'll ask which one you mean. You can");
+    println!("also tell it with its username, like github:alice.");
+    println!("");
     println!("Example:");
     println!("    rooster rename youtube Dailymotion");
 }
 
 pub fn callback_exec(matches: &getopts::Matches,
-                     store: &mut password::v2::PasswordStore)
+                     store: &mut password::PasswordStore)
                      -> Result<(), i32> {
     if matches.free.len() < 3 {
         println_err!("Woops, seems like the app name is missing here. For help, try:");
@@ -39,15 +43,15 @@ pub fn callback_exec(matches: &getopts::Matches,
     let old_name = matches.free[1].clone();
     let new_name = matches.free[2].clone();
 
-    let change_result = store.change_password(old_name.deref(),
-                                              &|old_password: password::v2::Password| {
-        password::v2::Password {
-            name: new_name.clone(),
-            username: old_password.username.clone(),
-            password: old_password.password.clone(),
-            created_at: old_password.created_at,
-            updated_at: ffi::time(),
-        }
+    let current = choose_password(store, old_name.deref())?;
+
+    let change_result = store.change_password(current.name.deref(),
+                                              current.username.deref(),
+                                              &|old_password: password::Password| {
+        let mut new_password = old_password.clone();
+        new_password.name = new_name.clone();
+        new_password.updated_at = timestamp::now();
+        new_password
     });
 
     match change_result {
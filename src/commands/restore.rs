@@ -0,0 +1,73 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::super::getopts;
+use super::super::password;
+use std::io::Write;
+
+pub fn callback_help() {
+    println!("Usage:");
+    println!("    rooster restore -h");
+    println!("    rooster restore <app_name> ...");
+    println!("");
+    println!("Brings deleted passwords back from the trash. If you deleted several accounts for");
+    println!("the app, tell which one with its username, like github:alice.");
+    println!("");
+    println!("Example:");
+    println!("    rooster restore youtube");
+}
+
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::PasswordStore)
+                     -> Result<(), i32> {
+    if matches.free.len() < 2 {
+        println_err!("Woops, seems like the app name is missing here. For help, try:");
+        println_err!("    rooster restore -h");
+        return Err(1);
+    }
+
+    let mut has_error = false;
+
+    for app_name in &matches.free[1..] {
+        match store.restore_password(app_name) {
+            Ok(password) => {
+                println_ok!("Done! I've brought \"{}\" back from the trash.", password.account());
+            }
+            Err(password::PasswordError::NoSuchAppError) => {
+                println_err!("Woops, \"{}\" isn't in the trash. To see what is, try:", app_name);
+                println_err!("    rooster trash list");
+                has_error = true;
+            }
+            Err(password::PasswordError::AmbiguousAppError) => {
+                println_err!("Woops, there are several accounts for \"{}\" in the trash. Tell \
+                              me which one with its username, like {}:<username>.",
+                             app_name,
+                             app_name);
+                has_error = true;
+            }
+            Err(password::PasswordError::AppExistsError) => {
+                println_err!("Woops, there is already a password for this account of \"{}\". \
+                              Rename or delete it first.",
+                             app_name);
+                has_error = true;
+            }
+            Err(err) => {
+                println_err!("Woops, I couldn't restore \"{}\" (reason: {:?}).", app_name, err);
+                has_error = true;
+            }
+        }
+    }
+
+    if has_error { Err(1) } else { Ok(()) }
+}
@@ -22,27 +22,41 @@ use std::ops::Deref;
 pub fn callback_help() {
     println!("Usage:");
     println!("    rooster search -h");
-    println!("    rooster search <query>");
+    println!("    rooster search [--tag <tag> ...] <query>");
+    println!("    rooster search --tag <tag> [--tag <tag> ...]");
+    println!("");
+    println!("With --tag, only passwords with all of the given tags are found.");
     println!("");
     println!("Example if you want to list all Google accounts:");
     println!("    rooster search google");
+    println!("");
+    println!("Example if you want to list all Google accounts for work:");
+    println!("    rooster search --tag work google");
 }
 
 pub fn callback_exec(matches: &getopts::Matches,
-                     store: &mut password::v2::PasswordStore)
+                     store: &mut password::PasswordStore)
                      -> Result<(), i32> {
-    if matches.free.len() < 2 {
+    let tags = matches.opt_strs("tag");
+    if matches.free.len() < 2 && tags.is_empty() {
         println_err!("Woops, seems like the app name is missing here. For help, try:");
         println_err!("    rooster search -h");
         return Err(1);
     }
 
-    let query = matches.free[1].clone();
+    // Without a query, every password with the tags is found.
+    let query = matches.free.get(1).cloned().unwrap_or(String::new());
 
-    let passwords = store.search_passwords(query.as_str());
+    let passwords = store.search_passwords(query.as_str(), tags.deref());
 
     if passwords.len() == 0 {
-        println_stderr!("I can't find any passwords for \"{}\"", query);
+        if tags.is_empty() {
+            println_stderr!("I can't find any passwords for \"{}\"", query);
+        } else {
+            println_stderr!("I can't find any passwords for \"{}\" tagged {}",
+                            query,
+                            tags.join(", "));
+        }
         return Ok(());
     }
 
@@ -57,7 +71,7 @@ pub fn callback_exec(matches: &getopts::Matches,
         println!("{:3} {:width$} {:30}",
                  i + 1,
                  p.name,
-                 p.username,
+                 if p.is_note() { "(secure note)" } else { p.username.as_str() },
                  width = longest_app_name);
     }
     println_stderr!("");
@@ -103,9 +117,7 @@ pub fn callback_exec(matches: &getopts::Matches,
         break;
     }
 
-    let app_name = passwords[index - 1].name.as_str();
-    // This whould never fail, since we've just checked that this password exists
-    let password = store.get_password(app_name).unwrap();
+    let password = passwords[index - 1];
     if show {
         println_ok!("Alright! Here is your password for {}: {}",
                     password.name,
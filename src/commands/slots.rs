@@ -0,0 +1,201 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::super::getopts;
+use super::super::password;
+use super::super::password::slot::{SlotKind, format_recovery_key};
+use super::{read_keyfile_from_matches, show_once_saved, warn_about_backups};
+use super::super::rpassword::prompt_password_stderr;
+use super::super::safe_string::SafeString;
+use std::io::Write;
+use std::iter::{Iterator, FromIterator, repeat};
+use std::ops::Deref;
+
+pub fn callback_help() {
+    println!("Usage:");
+    println!("    rooster slots -h");
+    println!("    rooster slots list");
+    println!("    rooster slots add <label> [--keyfile <path> | --no-keyfile]");
+    println!("    rooster slots add-recovery <label>");
+    println!("    rooster slots remove <label>");
+    println!("");
+    println!("Your password file can be opened in several ways, each with its own key slot.");
+    println!("The \"master\" slot is created along with the password file. You can add slots");
+    println!("with other passwords, or with a random recovery key to keep somewhere safe.");
+    println!("Type the recovery key when asked for your master password to use it.");
+    println!("");
+    println!("A password slot needs the keyfile given with --keyfile, unless you pass");
+    println!("--no-keyfile. You can't remove the slot you opened the password file with.");
+    println!("");
+    println!("Example:");
+    println!("    rooster slots list");
+    println!("    rooster slots add laptop");
+    println!("    rooster slots add-recovery office-safe");
+    println!("    rooster slots remove laptop");
+}
+
+fn label_from_matches(matches: &getopts::Matches) -> Result<String, i32> {
+    match matches.free.get(2) {
+        Some(label) => Ok(label.clone()),
+        None => {
+            println_err!("Woops, seems like the slot label is missing here. For help, try:");
+            println_err!("    rooster slots -h");
+            Err(1)
+        }
+    }
+}
+
+fn list(store: &password::PasswordStore) -> Result<(), i32> {
+    let horizontal_border = String::from_iter(repeat('-').take(73));
+
+    println!("{}", horizontal_border);
+    println!("| {:2} | {:30} | {:30} |", "id", "label", "unlocks with");
+    println!("{}", horizontal_border);
+    for (i, slot) in store.get_slots().iter().enumerate() {
+        let unlocks_with = match slot.get_kind() {
+            SlotKind::Password { keyfile: true, .. } => "password and keyfile",
+            SlotKind::Password { keyfile: false, .. } => "password",
+            SlotKind::RecoveryKey => "recovery key",
+        };
+        let label = if i == store.get_current_slot() {
+            format!("{} (in use)", slot.get_label())
+        } else {
+            slot.get_label().to_owned()
+        };
+        println!("| {:2?} | {:30} | {:30} |", i, label, unlocks_with);
+    }
+    println!("{}", horizontal_border);
+
+    Ok(())
+}
+
+fn add(matches: &getopts::Matches, store: &mut password::PasswordStore) -> Result<(), i32> {
+    let label = label_from_matches(matches)?;
+
+    let keyfile = if matches.opt_present("no-keyfile") {
+        None
+    } else {
+        read_keyfile_from_matches(matches)?
+    };
+
+    let password = match prompt_password_stderr("Type the password for this slot: ") {
+        Ok(password) => SafeString::new(password),
+        Err(err) => {
+            println_err!("I could not read the password (reason: {:?}).", err);
+            return Err(1);
+        }
+    };
+
+    let password_confirmation = match prompt_password_stderr("Type the password for this slot \
+                                                              once more: ") {
+        Ok(password_confirmation) => SafeString::new(password_confirmation),
+        Err(err) => {
+            println_err!("I could not read the password (reason: {:?}).", err);
+            return Err(1);
+        }
+    };
+
+    if password != password_confirmation {
+        println_err!("The password confirmation did not match. Aborting.");
+        return Err(1);
+    }
+
+    match store.add_password_slot(label.deref(), password.deref(), keyfile) {
+        Ok(()) => {
+            println_ok!("Done! The password file can now be opened with the password of slot \
+                         \"{}\".",
+                        label);
+            Ok(())
+        }
+        Err(password::PasswordError::SlotExistsError) => {
+            println_err!("Woops, there is already a slot named \"{}\".", label);
+            Err(1)
+        }
+        Err(err) => {
+            println_err!("Woops, I could not add the slot (reason: {:?}).", err);
+            Err(1)
+        }
+    }
+}
+
+fn add_recovery(matches: &getopts::Matches,
+                store: &mut password::PasswordStore)
+                -> Result<(), i32> {
+    let label = label_from_matches(matches)?;
+
+    match store.add_recovery_key_slot(label.deref()) {
+        Ok(recovery_key) => {
+            show_once_saved("Here is your recovery key. Write it down and keep it somewhere \
+                             safe. I won't be able to show it again.",
+                            vec![format_recovery_key(recovery_key.deref())]);
+            Ok(())
+        }
+        Err(password::PasswordError::SlotExistsError) => {
+            println_err!("Woops, there is already a slot named \"{}\".", label);
+            Err(1)
+        }
+        Err(err) => {
+            println_err!("Woops, I could not add the slot (reason: {:?}).", err);
+            Err(1)
+        }
+    }
+}
+
+fn remove(matches: &getopts::Matches, store: &mut password::PasswordStore) -> Result<(), i32> {
+    let label = label_from_matches(matches)?;
+
+    match store.remove_slot(label.deref()) {
+        Ok(()) => {
+            println_ok!("Done! I've removed the slot \"{}\".", label);
+            warn_about_backups("open with it, since they were made before it was removed");
+            Ok(())
+        }
+        Err(password::PasswordError::NoSuchSlotError) => {
+            println_err!("Woops, I couldn't find a slot named \"{}\".", label);
+            Err(1)
+        }
+        Err(password::PasswordError::SlotInUseError) => {
+            println_err!("Woops, you opened the password file with slot \"{}\", so I can't \
+                          remove it. Open the password file another way first.",
+                         label);
+            Err(1)
+        }
+        Err(password::PasswordError::LastPasswordSlotError) => {
+            println_err!("Woops, \"{}\" is the last password slot. I won't remove it, or only \
+                          recovery keys could open the password file.",
+                         label);
+            Err(1)
+        }
+        Err(err) => {
+            println_err!("Woops, I could not remove the slot (reason: {:?}).", err);
+            Err(1)
+        }
+    }
+}
+
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::PasswordStore)
+                     -> Result<(), i32> {
+    match matches.free.get(1).map(|s| s.as_str()) {
+        None | Some("list") => list(store),
+        Some("add") => add(matches, store),
+        Some("add-recovery") => add_recovery(matches, store),
+        Some("remove") => remove(matches, store),
+        Some(subcommand) => {
+            println_err!("Woops, I don't know how to \"{}\" slots. For help, try:", subcommand);
+            println_err!("    rooster slots -h");
+            Err(1)
+        }
+    }
+}
@@ -0,0 +1,91 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::super::getopts;
+use super::super::password;
+use super::super::password::slot::format_recovery_key_share;
+use super::super::shamir;
+use super::show_once_saved;
+use std::io::Write;
+use std::ops::Deref;
+
+pub fn callback_help() {
+    println!("Usage:");
+    println!("    rooster split-recovery -h");
+    println!("    rooster split-recovery <label> <threshold> <shares>");
+    println!("");
+    println!("Adds a recovery key slot, like `rooster slots add-recovery` does, but instead of");
+    println!("showing the recovery key, splits it into <shares> shares. Any <threshold> of them");
+    println!("can rebuild the recovery key, fewer can't tell anything about it. Give each share");
+    println!("to a different person. Together, they can set a new master password with:");
+    println!("    rooster recover");
+    println!("");
+    println!("Example:");
+    println!("    rooster split-recovery team 3 5");
+}
+
+fn parse_count(count: Option<&String>) -> Option<u8> {
+    count.and_then(|count| count.parse::<u8>().ok())
+}
+
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::PasswordStore)
+                     -> Result<(), i32> {
+    if matches.free.len() < 4 {
+        println_err!("Woops, seems like the label, the threshold or the number of shares is \
+                      missing here. For help, try:");
+        println_err!("    rooster split-recovery -h");
+        return Err(1);
+    }
+
+    let label = &matches.free[1];
+    let (threshold, num_shares) = match (parse_count(matches.free.get(2)),
+                                         parse_count(matches.free.get(3))) {
+        (Some(threshold), Some(num_shares)) if 2 <= threshold && threshold <= num_shares => {
+            (threshold, num_shares)
+        }
+        _ => {
+            println_err!("Woops, the threshold must be at least 2, and the number of shares at \
+                          least the threshold and at most 255.");
+            return Err(1);
+        }
+    };
+
+    let recovery_key = match store.add_recovery_key_slot(label.deref()) {
+        Ok(recovery_key) => recovery_key,
+        Err(password::PasswordError::SlotExistsError) => {
+            println_err!("Woops, there is already a slot named \"{}\".", label);
+            return Err(1);
+        }
+        Err(err) => {
+            println_err!("Woops, I could not add the slot (reason: {:?}).", err);
+            return Err(1);
+        }
+    };
+
+    let shares = match shamir::split(recovery_key.deref(), threshold, num_shares) {
+        Ok(shares) => shares,
+        Err(err) => {
+            println_err!("Woops, I could not split the recovery key (reason: {}).", err);
+            return Err(1);
+        }
+    };
+
+    show_once_saved(&format!("Here are the shares of the recovery key. Any {} of them can \
+                              open your password file. Give each one to a different person. I \
+                              won't be able to show them again.",
+                             threshold),
+                    shares.iter().map(format_recovery_key_share).collect());
+    Ok(())
+}
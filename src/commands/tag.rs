@@ -0,0 +1,142 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::super::getopts;
+use super::super::password;
+use super::choose_password;
+use std::io::Write;
+use std::iter::{Iterator, FromIterator, repeat};
+use std::ops::Deref;
+
+pub fn callback_help() {
+    println!("Usage:");
+    println!("    rooster tag -h");
+    println!("    rooster tag list");
+    println!("    rooster tag add <app_name> <tag>");
+    println!("    rooster tag remove <app_name> <tag>");
+    println!("");
+    println!("Tags sort your passwords, like \"work\" or \"personal\". A password can have any");
+    println!("number of them. To only see the passwords with some tags, try:");
+    println!("    rooster list --tag <tag>");
+    println!("    rooster search --tag <tag> <query>");
+    println!("");
+    println!("Example:");
+    println!("    rooster tag add youtube personal");
+    println!("    rooster tag remove youtube personal");
+}
+
+/// Lists every tag, with how many passwords have it.
+fn list(store: &password::PasswordStore) -> Result<(), i32> {
+    let mut tags: Vec<(String, usize)> = Vec::new();
+    for p in store.get_all_passwords().iter() {
+        for tag in p.tags.iter() {
+            match tags.iter().position(|&(ref t, _)| t.to_lowercase() == tag.to_lowercase()) {
+                Some(i) => tags[i].1 += 1,
+                None => tags.push((tag.clone(), 1)),
+            }
+        }
+    }
+
+    if tags.len() == 0 {
+        println!("No tags yet. Tag a password with 'rooster tag add <app> <tag>'.");
+        return Ok(());
+    }
+
+    tags.sort_by(|a, b| a.0.to_lowercase().cmp(&b.0.to_lowercase()));
+
+    let horizontal_border = String::from_iter(repeat('-').take(46));
+
+    println!("{}", horizontal_border);
+    println!("| {:30} | {:9} |", "tag", "passwords");
+    println!("{}", horizontal_border);
+    for &(ref tag, count) in tags.iter() {
+        println!("| {:30} | {:>9} |", tag, count);
+    }
+    println!("{}", horizontal_border);
+
+    Ok(())
+}
+
+fn change_tag(matches: &getopts::Matches,
+              store: &mut password::PasswordStore,
+              add: bool)
+              -> Result<(), i32> {
+    let (app_name, tag) = match (matches.free.get(2), matches.free.get(3)) {
+        (Some(app_name), Some(tag)) => (app_name, tag.trim()),
+        _ => {
+            println_err!("Woops, seems like the app name or the tag is missing here. For help, \
+                          try:");
+            println_err!("    rooster tag -h");
+            return Err(1);
+        }
+    };
+
+    if tag.is_empty() {
+        println_err!("Woops, tags can't be empty.");
+        return Err(1);
+    }
+
+    let password = choose_password(store, app_name)?;
+
+    if add && password.has_tag(tag) {
+        println_ok!("\"{}\" is already tagged {}.", password.name, tag);
+        return Ok(());
+    }
+    if !add && !password.has_tag(tag) {
+        println_ok!("\"{}\" isn't tagged {}.", password.name, tag);
+        return Ok(());
+    }
+
+    let change_result = store.change_password(password.name.deref(),
+                                              password.username.deref(),
+                                              &|old_password: password::Password| {
+        let mut new_password = old_password.clone();
+        if add {
+            new_password.add_tag(tag);
+        } else {
+            new_password.remove_tag(tag);
+        }
+        new_password
+    });
+
+    match change_result {
+        Ok(()) => {
+            if add {
+                println_ok!("Done! I've tagged \"{}\" {}.", password.name, tag);
+            } else {
+                println_ok!("Done! \"{}\" isn't tagged {} anymore.", password.name, tag);
+            }
+            Ok(())
+        }
+        Err(err) => {
+            println_err!("Woops, I couldn't save the tags (reason: {:?}).", err);
+            Err(1)
+        }
+    }
+}
+
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::PasswordStore)
+                     -> Result<(), i32> {
+    match matches.free.get(1).map(|s| s.as_str()) {
+        None | Some("list") => list(store),
+        Some("add") => change_tag(matches, store, true),
+        Some("remove") => change_tag(matches, store, false),
+        Some(subcommand) => {
+            println_err!("Woops, I don't know how to \"{}\" tags. For help, try:", subcommand);
+            println_err!("    rooster tag -h");
+            Err(1)
+        }
+    }
+}
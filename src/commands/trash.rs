@@ -0,0 +1,137 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::super::getopts;
+use super::super::password;
+use super::super::timestamp::{self, Timestamp};
+use super::warn_about_backups;
+use std::cmp;
+use std::env;
+use std::io::Write;
+use std::iter::{Iterator, FromIterator, repeat};
+
+/// Environment variable telling after how many days deleted passwords are purged. 0 purges
+/// them right away.
+pub const TRASH_DAYS_ENV_VAR: &'static str = "ROOSTER_TRASH_DAYS";
+
+/// How many days deleted passwords are kept when the environment variable isn't set.
+const DEFAULT_TRASH_DAYS: i64 = 30;
+
+pub fn callback_help() {
+    println!("Usage:");
+    println!("    rooster trash -h");
+    println!("    rooster trash list");
+    println!("    rooster trash empty");
+    println!("");
+    println!("Deleted passwords go to the trash, inside your password file, where they stay");
+    println!("encrypted. Bring one back with `rooster restore <app_name>`.");
+    println!("");
+    println!("They are purged {} days after being deleted, the next time your password file",
+             DEFAULT_TRASH_DAYS);
+    println!("changes. Set ${} to tell after how many days, 0 to purge them right away.",
+             TRASH_DAYS_ENV_VAR);
+    println!("");
+    println!("Example:");
+    println!("    rooster trash list");
+    println!("    rooster trash empty");
+}
+
+/// Purges the passwords that have been in the trash for longer than $ROOSTER_TRASH_DAYS says.
+pub fn purge_expired(store: &mut password::PasswordStore) -> Result<(), i32> {
+    let days = match env::var(TRASH_DAYS_ENV_VAR) {
+        Ok(days) => {
+            match days.parse::<i64>() {
+                Ok(days) if days >= 0 => days,
+                _ => {
+                    println_err!("Woops, ${} must be a number of days, for instance 30.",
+                                 TRASH_DAYS_ENV_VAR);
+                    return Err(1);
+                }
+            }
+        }
+        Err(_) => DEFAULT_TRASH_DAYS,
+    };
+
+    // With 0 days, everything goes, even what was deleted this very second.
+    let deleted_before = if days == 0 {
+        Timestamp::max_value()
+    } else {
+        timestamp::now().saturating_sub(days.saturating_mul(86400))
+    };
+    store.purge_trash(deleted_before);
+    Ok(())
+}
+
+fn list(store: &password::PasswordStore) -> Result<(), i32> {
+    let trash = store.get_trash();
+    if trash.len() == 0 {
+        println!("The trash is empty.");
+        return Ok(());
+    }
+
+    let app_width = trash.iter().fold(30, |w, d| cmp::max(w, d.password.name.chars().count()));
+    let username_width = trash.iter()
+        .fold(20, |w, d| cmp::max(w, d.password.username.chars().count()));
+    let horizontal_border = String::from_iter(repeat('-').take(app_width + username_width +
+                                                                 39));
+
+    println!("{}", horizontal_border);
+    println!("| {:app_width$} | {:username_width$} | {:27} |",
+             "app",
+             "username",
+             "deleted at",
+             app_width = app_width,
+             username_width = username_width);
+    println!("{}", horizontal_border);
+    // Most recently deleted first.
+    for deleted in trash.iter().rev() {
+        println!("| {:app_width$} | {:username_width$} | {:27} |",
+                 deleted.password.name,
+                 deleted.password.username,
+                 timestamp::format(deleted.deleted_at),
+                 app_width = app_width,
+                 username_width = username_width);
+    }
+    println!("{}", horizontal_border);
+
+    Ok(())
+}
+
+fn empty(store: &mut password::PasswordStore) -> Result<(), i32> {
+    match store.purge_trash(Timestamp::max_value()) {
+        0 => {
+            println_ok!("The trash is empty already.");
+            return Ok(());
+        }
+        1 => println_ok!("Done! I've purged 1 password from the trash."),
+        count => println_ok!("Done! I've purged {} passwords from the trash.", count),
+    }
+    warn_about_backups("hold the passwords that were in the trash");
+    Ok(())
+}
+
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::PasswordStore)
+                     -> Result<(), i32> {
+    match matches.free.get(1).map(|s| s.as_str()) {
+        None | Some("list") => list(store),
+        Some("empty") => empty(store),
+        Some(subcommand) => {
+            println_err!("Woops, I don't know how to \"{}\" the trash. For help, try:",
+                         subcommand);
+            println_err!("    rooster trash -h");
+            Err(1)
+        }
+    }
+}
@@ -0,0 +1,153 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::super::getopts;
+use super::super::password;
+use super::super::password::kdf::Kdf;
+use super::super::rpassword::prompt_password_stderr;
+use super::super::safe_string::SafeString;
+use std::io::Write;
+use std::io::stdin;
+use std::ops::Deref;
+use std::time::Duration;
+
+/// How long unlocking the password file should take by default, in milliseconds.
+const DEFAULT_TARGET_MILLIS: u64 = 1000;
+
+pub fn callback_help() {
+    println!("Usage:");
+    println!("    rooster tune-kdf -h");
+    println!("    rooster tune-kdf [<seconds>]");
+    println!("");
+    println!("Measures how fast your computer derives keys from your master password and");
+    println!("suggests stronger parameters so that unlocking takes about <seconds>, 1 by");
+    println!("default. If you agree, the password file is re-encrypted with them.");
+    println!("");
+    println!("Example:");
+    println!("    rooster tune-kdf");
+    println!("    rooster tune-kdf 2.5");
+}
+
+pub fn parse_target(seconds: &str) -> Option<Duration> {
+    match seconds.parse::<f64>() {
+        Ok(seconds) if seconds > 0.0 && seconds <= 60.0 => {
+            Some(Duration::from_millis((seconds * 1000.0) as u64))
+        }
+        _ => None,
+    }
+}
+
+fn duration_as_seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1_000_000_000.0
+}
+
+pub fn callback_exec(matches: &getopts::Matches,
+                     store: &mut password::PasswordStore)
+                     -> Result<(), i32> {
+    let target = match matches.free.get(1) {
+        Some(seconds) => {
+            match parse_target(seconds) {
+                Some(target) => target,
+                None => {
+                    println_err!("Woops, the unlock time must be a number of seconds between 0 \
+                                  and 60, for instance 1 or 2.5.");
+                    return Err(1);
+                }
+            }
+        }
+        None => Duration::from_millis(DEFAULT_TARGET_MILLIS),
+    };
+
+    let current_kdf = match store.get_kdf() {
+        Some(kdf) => kdf,
+        None => {
+            println_err!("Woops, you opened your password file with a recovery key, which \
+                          doesn't use key derivation. Open it with your master password \
+                          instead.");
+            return Err(1);
+        }
+    };
+
+    println_stderr!("Your password file currently uses {}.", current_kdf);
+    println_stderr!("Measuring how fast your computer derives keys, this may take a while...");
+
+    let (kdf, elapsed) = match Kdf::tune(target) {
+        Ok(tuned) => tuned,
+        Err(err) => {
+            println_err!("Woops, I could not measure key derivation (reason: {:?}).", err);
+            return Err(1);
+        }
+    };
+
+    if kdf == current_kdf {
+        println_ok!("Your password file already uses the parameters I would suggest.");
+        return Ok(());
+    }
+
+    println_stderr!("");
+    println_stderr!("I suggest {}.", kdf);
+    println_stderr!("Unlocking would take about {:.2} seconds on this computer.",
+                    duration_as_seconds(elapsed));
+    println_stderr!("");
+    println_stderr!("Re-encrypt your password file with these parameters? [y/n]");
+    loop {
+        let mut line = String::new();
+        match stdin().read_line(&mut line) {
+            Ok(0) => {
+                println_err!("Woops, I didn't get an answer. I've left your password file as it \
+                              was.");
+                return Err(1);
+            }
+            Ok(_) => {
+                if line.starts_with('y') {
+                    break;
+                } else if line.starts_with('n') {
+                    println_ok!("Alright, I've left your password file as it was.");
+                    return Ok(());
+                } else {
+                    println_stderr!("I did not get that. Re-encrypt your password file? [y/n]");
+                }
+            }
+            Err(err) => {
+                println_err!("I couldn't read that (reason: {}).", err);
+                return Err(1);
+            }
+        }
+    }
+
+    let master_password = match prompt_password_stderr("Type your master password once more: ") {
+        Ok(master_password) => SafeString::new(master_password),
+        Err(err) => {
+            println_err!("I could not read your master password (reason: {:?}).", err);
+            return Err(1);
+        }
+    };
+
+    match store.change_kdf(master_password.deref(), kdf) {
+        Ok(()) => {
+            println_ok!("Done! Your password file now uses {}.", kdf);
+            Ok(())
+        }
+        Err(password::PasswordError::WrongMasterPasswordError) => {
+            println_err!("That's not your master password. I've left your password file as it \
+                          was.");
+            Err(1)
+        }
+        Err(err) => {
+            println_err!("Woops, I could not re-encrypt your password file (reason: {:?}).",
+                         err);
+            Err(1)
+        }
+    }
+}
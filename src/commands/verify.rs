@@ -0,0 +1,332 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::super::byteorder::{ReadBytesExt, BigEndian};
+use super::super::getopts;
+use super::super::password;
+use super::super::password::slot::SlotKind;
+use super::super::password::v4::OpenError;
+use super::super::rpassword::prompt_password_stderr;
+use super::super::safe_string::SafeString;
+use super::super::safe_vec::SafeVec;
+use super::read_keyfile_from_matches;
+use std::fs::File;
+use std::io::{Cursor, Read, Write};
+use std::ops::Deref;
+
+pub fn callback_help() {
+    println!("Usage:");
+    println!("    rooster verify -h");
+    println!("    rooster verify [--keyfile <path>]");
+    println!("");
+    println!("Checks your password file without changing it. If it can't be opened, tells");
+    println!("exactly why. Otherwise, shows how it is encrypted, how many passwords it holds,");
+    println!("and whether some of them have empty or duplicate app names.");
+    println!("");
+    println!("Example:");
+    println!("    rooster verify");
+}
+
+/// Checks the app names, which must not be empty, and the accounts, which must be unique
+/// without regard to casing. Each account is an app name and a username.
+///
+/// Returns whether they are fine.
+fn check_names(accounts: &[(&str, &str)]) -> bool {
+    let mut ok = true;
+    for (i, &(name, username)) in accounts.iter().enumerate() {
+        if name.is_empty() {
+            println_err!("Woops, password {} has an empty app name.", i);
+            ok = false;
+        }
+        for &(other, other_username) in accounts[..i].iter() {
+            if other.to_lowercase() == name.to_lowercase() &&
+               other_username.to_lowercase() == username.to_lowercase() {
+                println_err!("Woops, \"{}\" and \"{}\" have the same app name and username, so \
+                              only one of them can be retrieved.",
+                             other,
+                             name);
+                ok = false;
+            }
+        }
+    }
+    ok
+}
+
+fn print_expensive_kdf_error() {
+    println_err!("Woops, the file asks for a key derivation that would take more than 4 GiB of \
+                  memory or 64 passes over 1 GiB, or for several that would take more than 256 \
+                  passes over 1 GiB all together. If it wasn't tampered with, check it anyway \
+                  with:");
+    println_err!("    rooster --allow-expensive-kdf verify");
+}
+
+fn verify_v2(master_password: &str, input: &[u8], allow_expensive: bool) -> Result<(), i32> {
+    println!("Format:     version 2, Rooster will upgrade it next time you open it");
+
+    let passwords = match password::v2::get_all_passwords(master_password,
+                                                          input,
+                                                          allow_expensive) {
+        Ok(passwords) => passwords,
+        Err(password::PasswordError::Io(_)) => {
+            println_err!("Woops, the file ends before its salt, IV or signature. It has been \
+                          truncated.");
+            return Err(1);
+        }
+        Err(password::PasswordError::ExpensiveKdfError) => {
+            print_expensive_kdf_error();
+            return Err(1);
+        }
+        Err(password::PasswordError::CorruptionError) => {
+            println_err!("Woops, the HMAC signature doesn't match. Either your master password \
+                          is wrong, or the file has been damaged.");
+            return Err(1);
+        }
+        Err(password::PasswordError::DecryptionError) => {
+            println_err!("Woops, the signature matches but the passwords could not be \
+                          decrypted.");
+            return Err(1);
+        }
+        Err(password::PasswordError::InvalidJsonError) => {
+            println_err!("Woops, the passwords were decrypted, but they are not valid JSON.");
+            return Err(1);
+        }
+        Err(err) => {
+            println_err!("Woops, I could not read the file (reason: {:?}).", err);
+            return Err(1);
+        }
+    };
+
+    println!("Passwords:  {}", passwords.len());
+    let accounts: Vec<(&str, &str)> =
+        passwords.iter().map(|p| (p.name.deref(), p.username.deref())).collect();
+    if check_names(accounts.deref()) { Ok(()) } else { Err(1) }
+}
+
+fn verify_v3(master_password: &str,
+             keyfile: Option<&[u8]>,
+             input: &[u8],
+             allow_expensive: bool)
+             -> Result<(), i32> {
+    println!("Format:     version 3, Rooster will upgrade it next time you open it");
+
+    let file = match password::v3::open(master_password, keyfile, input, allow_expensive) {
+        Ok(file) => file,
+        Err(password::PasswordError::Io(_)) => {
+            println_err!("Woops, the file ends in the middle of its header. It has been \
+                          truncated.");
+            return Err(1);
+        }
+        Err(password::PasswordError::KeyfileRequiredError) => {
+            println_err!("Woops, all key slots need a keyfile. Tell me where it is with:");
+            println_err!("    rooster --keyfile <path> verify");
+            return Err(1);
+        }
+        Err(password::PasswordError::ExpensiveKdfError) => {
+            print_expensive_kdf_error();
+            return Err(1);
+        }
+        Err(password::PasswordError::DecryptionError) => {
+            println_err!("Woops, no key slot opens with this master password and keyfile. \
+                          Either they are wrong, or the key slots have been damaged.");
+            return Err(1);
+        }
+        Err(password::PasswordError::CorruptionError) => {
+            println_err!("Woops, a key slot opened, but the passwords don't authenticate with \
+                          its key. The file has been damaged or tampered with.");
+            return Err(1);
+        }
+        Err(password::PasswordError::InvalidJsonError) => {
+            println_err!("Woops, the passwords were decrypted, but they are not valid JSON.");
+            return Err(1);
+        }
+        Err(err) => {
+            println_err!("Woops, I could not read the file (reason: {:?}).", err);
+            return Err(1);
+        }
+    };
+
+    println!("Key slots:  {}", file.slots.len());
+    println!("Passwords:  {}", file.passwords.len());
+    let accounts: Vec<(&str, &str)> =
+        file.passwords.iter().map(|p| (p.name.deref(), p.username.deref())).collect();
+    if check_names(accounts.deref()) { Ok(()) } else { Err(1) }
+}
+
+fn verify_v4(master_password: SafeString,
+             keyfile: Option<SafeVec>,
+             input: &[u8],
+             allow_expensive: bool)
+             -> Result<(), i32> {
+    println!("Format:     version 4");
+
+    let store = match password::v4::PasswordStore::open(master_password,
+                                                        keyfile,
+                                                        input,
+                                                        allow_expensive) {
+        Ok(store) => store,
+        Err(OpenError::Truncated) => {
+            println_err!("Woops, the file ends in the middle of its header. It has been \
+                          truncated.");
+            return Err(1);
+        }
+        Err(OpenError::WrongVersion(version)) => {
+            println_err!("Woops, the file says it is version {}, not 4.", version);
+            return Err(1);
+        }
+        Err(OpenError::UnknownFlags(flags)) => {
+            println_err!("Woops, the header has flags ({:#x}) only a newer Rooster knows \
+                          about.",
+                         flags);
+            return Err(1);
+        }
+        Err(OpenError::UnknownPaddingPolicy(policy)) => {
+            println_err!("Woops, the passwords are padded in a way ({}) only a newer Rooster \
+                          knows about.",
+                         policy);
+            return Err(1);
+        }
+        Err(OpenError::InvalidSlot(_, password::PasswordError::ExpensiveKdfError)) => {
+            print_expensive_kdf_error();
+            return Err(1);
+        }
+        Err(OpenError::InvalidSlot(i, err)) => {
+            println_err!("Woops, key slot {} is invalid (reason: {:?}).", i, err);
+            return Err(1);
+        }
+        Err(OpenError::KeyfileRequired) => {
+            println_err!("Woops, all key slots need a keyfile. Tell me where it is with:");
+            println_err!("    rooster --keyfile <path> verify");
+            return Err(1);
+        }
+        Err(OpenError::NoMatchingSlot) => {
+            println_err!("Woops, no key slot opens with this master password and keyfile. \
+                          Either they are wrong, or the key slots have been damaged.");
+            return Err(1);
+        }
+        Err(OpenError::AuthenticationFailed) => {
+            println_err!("Woops, a key slot opened, but the passwords don't authenticate with \
+                          its key. The file has been damaged or tampered with.");
+            return Err(1);
+        }
+        Err(OpenError::UnknownPayloadVersion(version)) => {
+            println_err!("Woops, the passwords were decrypted, but they are encoded in version \
+                          {}, which only a newer Rooster knows about.",
+                         version);
+            return Err(1);
+        }
+        Err(OpenError::InvalidPayload) => {
+            println_err!("Woops, the passwords were decrypted, but their encoding is invalid.");
+            return Err(1);
+        }
+    };
+
+    println!("Key slots:  {}", store.get_slots().len());
+    for (i, slot) in store.get_slots().iter().enumerate() {
+        let in_use = if i == store.get_current_slot() {
+            " (in use)"
+        } else {
+            ""
+        };
+        match slot.get_kind() {
+            SlotKind::Password { kdf, keyfile, .. } => {
+                println!("    {}{}: password{}, {}",
+                         slot.get_label(),
+                         in_use,
+                         if keyfile { " and keyfile" } else { "" },
+                         kdf);
+            }
+            SlotKind::RecoveryKey => {
+                println!("    {}{}: recovery key", slot.get_label(), in_use);
+            }
+        }
+    }
+
+    let passwords = store.get_all_passwords();
+    println!("Passwords:  {}", passwords.len());
+    let accounts: Vec<(&str, &str)> =
+        passwords.iter().map(|p| (p.name.deref(), p.username.deref())).collect();
+    if check_names(accounts.deref()) { Ok(()) } else { Err(1) }
+}
+
+pub fn callback_exec(matches: &getopts::Matches, filename: &str) -> Result<(), i32> {
+    let mut input: Vec<u8> = Vec::new();
+    if let Err(err) = File::open(filename).and_then(|mut file| file.read_to_end(&mut input)) {
+        println_err!("Woops, I could not read the password file at {} (reason: {}).",
+                     filename,
+                     err);
+        return Err(1);
+    }
+
+    if input.is_empty() {
+        println_err!("Woops, your password file is empty.");
+        return Err(1);
+    }
+
+    let version = match Cursor::new(input.deref()).read_u32::<BigEndian>() {
+        Ok(version) => version,
+        Err(_) => {
+            println_err!("Woops, your password file is too short to be a Rooster file.");
+            return Err(1);
+        }
+    };
+
+    // Read the keyfile before anything else, so we don't ask for the master password in vain.
+    let keyfile = read_keyfile_from_matches(matches)?;
+
+    let master_password = match prompt_password_stderr("Type your master password: ") {
+        Ok(master_password) => SafeString::new(master_password),
+        Err(err) => {
+            println_err!("Woops, I could not read your master password (reason: {}).", err);
+            return Err(1);
+        }
+    };
+
+    let allow_expensive = matches.opt_present("allow-expensive-kdf");
+    let result = match version {
+        2 => verify_v2(master_password.deref(), input.deref(), allow_expensive),
+        3 => {
+            verify_v3(master_password.deref(),
+                      keyfile.as_ref().map(|k| k.deref()),
+                      input.deref(),
+                      allow_expensive)
+        }
+        4 => verify_v4(master_password, keyfile, input.deref(), allow_expensive),
+        _ => {
+            // The very first format has no header, so it can only be recognized by opening it.
+            match password::v1::get_all_passwords(master_password.deref(), input.deref()) {
+                Ok(passwords) => {
+                    println!("Format:     version 1, Rooster will upgrade it next time you open \
+                              it");
+                    println!("Passwords:  {}", passwords.len());
+                    let accounts: Vec<(&str, &str)> = passwords.iter()
+                        .map(|p| (p.name.deref(), p.username.deref()))
+                        .collect();
+                    if check_names(accounts.deref()) { Ok(()) } else { Err(1) }
+                }
+                Err(_) => {
+                    println_err!("Woops, the file says it is version {}, which I don't know. \
+                                  Either it comes from a newer Rooster, or it is not a Rooster \
+                                  file.",
+                                 version);
+                    Err(1)
+                }
+            }
+        }
+    };
+
+    if result.is_ok() {
+        println_ok!("Everything looks fine.");
+    }
+    result
+}
@@ -12,9 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use super::getopts;
 use super::rand::{Rng, OsRng};
-use std::io::{Write, Result as IoResult};
+use std::io::{Error as IoError, ErrorKind as IoErrorKind, Result as IoResult};
 
 fn generate_password(alnum: bool, len: usize) -> IoResult<String> {
     let mut password_as_string = String::new();
@@ -47,7 +46,14 @@ fn password_is_hard(password: &str, alnum: bool) -> bool {
     (alnum || password.find(is_punctuation).is_some())
 }
 
+/// Generates a random password of `len` characters, with at least one digit, one uppercase
+/// letter, one lowercase letter and, unless `alnum` is set, one punctuation character.
+///
+/// So `len` must be at least 4.
 pub fn generate_hard_password(alnum: bool, len: usize) -> IoResult<String> {
+    if len < 4 {
+        return Err(IoError::new(IoErrorKind::InvalidInput, "password length must be at least 4"));
+    }
     loop {
         let password = generate_password(alnum, len)?;
         if password_is_hard(password.as_ref(), alnum) {
@@ -56,40 +62,8 @@ pub fn generate_hard_password(alnum: bool, len: usize) -> IoResult<String> {
     }
 }
 
+/// What kind of password to generate, see `generate_hard_password`.
 pub struct PasswordSpec {
     pub alnum: bool,
     pub len: usize,
 }
-
-impl PasswordSpec {
-    pub fn from_matches(matches: &getopts::Matches) -> Option<PasswordSpec> {
-        let alnum = matches.opt_present("alnum");
-        let mut password_len = 32;
-        if let Some(len) = matches.opt_str("length") {
-            password_len = match len.parse::<usize>() {
-                Ok(parsed_len) => {
-                    // We want passwords to contain at least one uppercase letter, one lowercase
-                    // letter and one digit. So we need at least 4 characters for each password.
-                    // This checks makes sure we don't run into an infinite loop trying to generate
-                    // a password of length <4 with 4 different kinds of characters (uppercase,
-                    // lowercase, numeric, punctuation).
-                    if parsed_len < 4 {
-                        println_err!("Woops! The length of the password must be at least 4. This");
-                        println_err!("allows us to make sure your password is secure.");
-                        return None;
-                    }
-                    parsed_len
-                }
-                Err(_) => {
-                    println_err!("Woops! The length option must be a valid number, for instance \
-                                  8 or 16.");
-                    return None;
-                }
-            }
-        }
-        Some(PasswordSpec {
-            alnum: alnum,
-            len: password_len,
-        })
-    }
-}
@@ -0,0 +1,88 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Reads and writes Rooster password files.
+//!
+//! This is what the `rooster` command is built upon, so other programs can use password files
+//! without going through it. A password file is opened into a `password::PasswordStore`,
+//! changed in memory, then saved back:
+//!
+//! ```no_run
+//! extern crate rooster;
+//!
+//! use rooster::password::{Password, PasswordStore};
+//! use rooster::safe_string::SafeString;
+//! use rooster::safe_vec::SafeVec;
+//! use std::fs::File;
+//! use std::io::Read;
+//! use std::path::Path;
+//! use std::time::Duration;
+//!
+//! # fn main() {
+//! let path = Path::new("/home/me/.passwords.rooster");
+//!
+//! // Other Rooster processes wait for the lock, so they don't overwrite our changes.
+//! let _lock = rooster::lock::lock_password_file(path, Duration::from_secs(10)).unwrap();
+//!
+//! let mut input = Vec::new();
+//! File::open(path).unwrap().read_to_end(&mut input).unwrap();
+//!
+//! let master_password = SafeString::new("my master password".to_owned());
+//! let mut store = PasswordStore::from_input(master_password, None, SafeVec::new(input.clone()))
+//!     .unwrap();
+//!
+//! for password in store.get_all_passwords() {
+//!     println!("{}: {}", password.name, password.username);
+//! }
+//!
+//! store.add_password(Password::new("GitHub".to_owned(),
+//!                                  "me@example.com".to_owned(),
+//!                                  SafeString::new("hunter2".to_owned())))
+//!     .unwrap();
+//!
+//! // Keep the file as it was, like the `rooster` command does, then replace it.
+//! rooster::backup::create_backup(path, &input, 10).unwrap();
+//! store.sync(path).unwrap();
+//! # }
+//! ```
+//!
+//! Files written by older versions of Rooster can be opened with `password::upgrade`.
+
+extern crate libc;
+extern crate rustc_serialize;
+extern crate crypto;
+extern crate rand;
+extern crate byteorder;
+extern crate argon2;
+extern crate chacha20poly1305;
+
+mod aes;
+mod aead;
+
+/// Copies of the password file kept before it changes.
+pub mod backup;
+/// Random passwords.
+pub mod generate;
+/// Locking the password file against other Rooster processes.
+pub mod lock;
+/// Opening, changing and saving password files.
+pub mod password;
+/// Strings that are wiped from memory when dropped.
+pub mod safe_string;
+/// Byte buffers that are wiped from memory when dropped.
+pub mod safe_vec;
+/// Splitting recovery keys into shares.
+pub mod shamir;
+/// Timestamps of passwords.
+pub mod timestamp;
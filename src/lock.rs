@@ -0,0 +1,139 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fs::{File, OpenOptions};
+use std::io::{Error as IoError, ErrorKind as IoErrorKind, Result as IoResult};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long to sleep between two attempts when waiting for the lock.
+const RETRY_INTERVAL_MILLIS: u64 = 100;
+
+#[derive(Debug)]
+pub enum LockError {
+    /// Another process holds the lock.
+    Busy,
+    Io(IoError),
+}
+
+impl From<IoError> for LockError {
+    fn from(err: IoError) -> LockError {
+        LockError::Io(err)
+    }
+}
+
+/// An exclusive lock on a password file, released when dropped.
+///
+/// The lock is taken on a separate lock file, not on the password file itself. Saving the
+/// password file replaces it with a new one, which would not be locked.
+pub struct PasswordFileLock {
+    // Closing the file releases the lock.
+    _file: File,
+}
+
+fn lock_path(path: &Path) -> IoResult<PathBuf> {
+    let file_name = match path.file_name() {
+        Some(file_name) => file_name.to_string_lossy().into_owned(),
+        None => return Err(IoError::new(IoErrorKind::Other, "invalid password file path")),
+    };
+    Ok(path.with_file_name(format!(".{}.lock", file_name)))
+}
+
+#[cfg(unix)]
+fn try_lock(file: &File) -> IoResult<bool> {
+    use super::libc;
+    use std::os::unix::io::AsRawFd;
+
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let err = IoError::last_os_error();
+    if err.raw_os_error() == Some(libc::EWOULDBLOCK) {
+        Ok(false)
+    } else {
+        Err(err)
+    }
+}
+
+/// There is no flock on Windows, so there is no locking there yet.
+#[cfg(not(unix))]
+fn try_lock(_file: &File) -> IoResult<bool> {
+    Ok(true)
+}
+
+/// Locks the password file at `path`, so no other Rooster process can use it until the lock
+/// is dropped.
+///
+/// If another process holds the lock, this waits up to `wait` for it to be released, then
+/// gives up with `LockError::Busy`.
+pub fn lock_password_file(path: &Path, wait: Duration) -> Result<PasswordFileLock, LockError> {
+    let file = OpenOptions::new().write(true).create(true).open(lock_path(path)?)?;
+
+    let start = Instant::now();
+    loop {
+        if try_lock(&file)? {
+            return Ok(PasswordFileLock { _file: file });
+        }
+        if start.elapsed() >= wait {
+            return Err(LockError::Busy);
+        }
+        thread::sleep(Duration::from_millis(RETRY_INTERVAL_MILLIS));
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    fn test_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("rooster-test-{}-{}.rooster", process::id(), name))
+    }
+
+    #[test]
+    fn lock_is_busy_while_held() {
+        let path = test_path("lock-busy");
+
+        let lock = lock_password_file(&path, Duration::from_millis(0)).unwrap();
+        let start = Instant::now();
+        match lock_password_file(&path, Duration::from_millis(300)) {
+            Err(LockError::Busy) => {}
+            other => panic!("expected the lock to be busy, got {:?}", other.err()),
+        }
+        assert!(start.elapsed() >= Duration::from_millis(300));
+
+        drop(lock);
+        assert!(lock_password_file(&path, Duration::from_millis(0)).is_ok());
+
+        fs::remove_file(lock_path(&path).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn lock_waits_for_release() {
+        let path = test_path("lock-wait");
+
+        let lock = lock_password_file(&path, Duration::from_millis(0)).unwrap();
+        let holder = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            drop(lock);
+        });
+        assert!(lock_password_file(&path, Duration::from_secs(10)).is_ok());
+        holder.join().unwrap();
+
+        fs::remove_file(lock_path(&path).unwrap()).unwrap();
+    }
+}
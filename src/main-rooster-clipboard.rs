@@ -13,17 +13,22 @@
 // limitations under the License.
 
 extern crate clipboard;
+extern crate rooster;
 #[cfg(target_os="linux")]
 extern crate unix_daemonize;
 
-fn do_copy(data: String) -> Result<clipboard::ClipboardContext, ()> {
+use rooster::safe_string::SafeString;
+use std::ops::Deref;
+
+fn do_copy(data: &SafeString) -> Result<clipboard::ClipboardContext, ()> {
     let mut context = clipboard::ClipboardContext::new().map_err(|_| ())?;
-    context.set_contents(data).map_err(|_| ())?;
+    context.set_contents(data.deref().to_owned()).map_err(|_| ())?;
     Ok(context)
 }
 
-fn get_data_from_args() -> String {
-    std::env::args().nth(1).unwrap()
+// The data is a password, so we keep it where it is wiped once we're done.
+fn get_data_from_args() -> SafeString {
+    SafeString::new(std::env::args().nth(1).unwrap())
 }
 
 // On Linux, using X, we need to run the clipboard handler in a subprocess because
@@ -36,7 +41,7 @@ fn main() {
         .unwrap();
 
     let data = get_data_from_args();
-    let context = do_copy(data.clone()).unwrap();
+    let context = do_copy(&data).unwrap();
 
     // Keep the process alive as long as the data is still in the clipboard, which means no other
     // copy has been made.
@@ -47,7 +52,8 @@ fn main() {
             // If the clipboard has been set by another program, we'll stop this
             // background process.
             Ok(data_in_clipboard) => {
-                if data_in_clipboard != data {
+                let data_in_clipboard = SafeString::new(data_in_clipboard);
+                if data_in_clipboard.deref() != data.deref() {
                     break;
                 }
             }
@@ -60,5 +66,5 @@ fn main() {
 
 #[cfg(not(target_os="linux"))]
 fn main() {
-    do_copy(get_data_from_args()).unwrap();
+    do_copy(&get_data_from_args()).unwrap();
 }
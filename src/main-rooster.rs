@@ -14,15 +14,14 @@
 
 // #![allow(useless_format, too_many_arguments)]
 
+extern crate rooster;
 extern crate libc;
 extern crate getopts;
 extern crate rustc_serialize;
-extern crate crypto;
 extern crate rpassword;
-extern crate rand;
 extern crate byteorder;
 
-use std::fs::File;
+use std::fs::{self, File};
 use std::env;
 use std::env::VarError;
 use std::path::MAIN_SEPARATOR as PATH_SEP;
@@ -30,23 +29,16 @@ use std::io::Result as IoResult;
 use std::io::Error as IoError;
 use std::io::ErrorKind as IoErrorKind;
 use std::io::Write;
-use std::io::Read;
 use std::path::{Path, PathBuf};
 use getopts::Options;
 use rpassword::prompt_password_stderr;
+use rooster::{backup, generate, lock, password, safe_string, safe_vec, shamir, timestamp};
 use safe_string::SafeString;
-use safe_vec::SafeVec;
 use std::ops::Deref;
 
 mod macros;
-mod aes;
 mod commands;
-mod ffi;
-mod password;
 mod color;
-mod safe_string;
-mod safe_vec;
-mod generate;
 mod clipboard;
 
 const ROOSTER_FILE_ENV_VAR: &'static str = "ROOSTER_FILE";
@@ -54,78 +46,6 @@ const ROOSTER_FILE_DEFAULT: &'static str = ".passwords.rooster";
 const DONT_CREATE_PASSWORD_FILE: &'static str = "DONT_CREATE_PASSWORD_FILE";
 const FAIL_READING_NEW_PASSWORD: &'static str = "FAIL_READING_NEW_PASSWORD";
 
-struct Command {
-    name: &'static str,
-    callback_exec: fn(&getopts::Matches, &mut password::v2::PasswordStore) -> Result<(), i32>,
-    callback_help: fn(),
-}
-
-static COMMANDS: &'static [Command] =
-    &[Command {
-          name: "get",
-          callback_exec: commands::get::callback_exec,
-          callback_help: commands::get::callback_help,
-      },
-      Command {
-          name: "add",
-          callback_exec: commands::add::callback_exec,
-          callback_help: commands::add::callback_help,
-      },
-      Command {
-          name: "delete",
-          callback_exec: commands::delete::callback_exec,
-          callback_help: commands::delete::callback_help,
-      },
-      Command {
-          name: "generate",
-          callback_exec: commands::generate::callback_exec,
-          callback_help: commands::generate::callback_help,
-      },
-      Command {
-          name: "regenerate",
-          callback_exec: commands::regenerate::callback_exec,
-          callback_help: commands::regenerate::callback_help,
-      },
-      Command {
-          name: "list",
-          callback_exec: commands::list::callback_exec,
-          callback_help: commands::list::callback_help,
-      },
-      Command {
-          name: "export",
-          callback_exec: commands::export::callback_exec,
-          callback_help: commands::export::callback_help,
-      },
-      Command {
-          name: "change-master-password",
-          callback_exec: commands::change_master_password::callback_exec,
-          callback_help: commands::change_master_password::callback_help,
-      },
-      Command {
-          name: "rename",
-          callback_exec: commands::rename::callback_exec,
-          callback_help: commands::rename::callback_help,
-      },
-      Command {
-          name: "change",
-          callback_exec: commands::change::callback_exec,
-          callback_help: commands::change::callback_help,
-      },
-      Command {
-          name: "search",
-          callback_exec: commands::search::callback_exec,
-          callback_help: commands::search::callback_help,
-      }];
-
-fn command_from_name(name: &str) -> Option<&'static Command> {
-    for c in COMMANDS.iter() {
-        if c.name == name {
-            return Some(c);
-        }
-    }
-    None
-}
-
 fn open_password_file(filename: &str, create: bool) -> IoResult<File> {
     let mut options = std::fs::OpenOptions::new();
     options.read(true);
@@ -188,57 +108,6 @@ fn get_password_file(filename: &str) -> IoResult<(Option<SafeString>, File)> {
     }
 }
 
-fn execute_command_from_filename(matches: &getopts::Matches,
-                                 command: &Command,
-                                 file: &mut File,
-                                 master_password: SafeString)
-                                 -> Result<(), i32> {
-
-    let mut input: Vec<u8> = Vec::new();
-    file.read_to_end(&mut input).map_err(|_| 1)?;
-
-    // If the password file is empty (ie new), we'll make a new, empty store.
-    let mut store = if input.is_empty() {
-        password::v2::PasswordStore::new(master_password.clone()).map_err(|_| 1)?
-    } else {
-        // Try to open the file as is.
-        match password::v2::PasswordStore::from_input(master_password.clone(),
-                                                      SafeVec::new(input.clone())) {
-            Ok(store) => store,
-            Err(_) => {
-                // If we can't open the file, we may need to upgrade its format first.
-                match password::upgrade(master_password.clone(), SafeVec::new(input.clone())) {
-                    Ok(store) => store,
-                    Err(_) => {
-                        // If we can't upgrade its format either, we show a helpful
-                        // error message.
-                        println_err!("I could not upgrade the Rooster file. This \
-                                      could be because:");
-                        println_err!("- you explicitly told Rooster not to open the \
-                                      file,");
-                        println_err!("- your version of Rooster is outdated,");
-                        println_err!("- your Rooster file is corrupted,");
-                        println_err!("- your master password is wrong.");
-                        println_err!("Try upgrading to the latest version of Rooster.");
-                        return Err(1);
-                    }
-                }
-            }
-        }
-    };
-
-    // Execute the command and save the new password list
-    (command.callback_exec)(matches, &mut store)?;
-
-    match store.sync(file) {
-        Ok(()) => { Ok(()) }
-        Err(err) => {
-            println_err!("I could not save the password file (reason: {:?}).", err);
-            Err(1)
-        }
-    }
-}
-
 fn get_password_file_path(rooster_file: Result<String, VarError>,
                           home_dir: Option<PathBuf>)
                           -> Result<String, i32> {
@@ -259,6 +128,19 @@ fn get_password_file_path(rooster_file: Result<String, VarError>,
     }
 }
 
+/// Where the password file is, from $ROOSTER_FILE or in the home directory.
+///
+/// If that's a symbolic link, this is the file it leads to. That's the file saving replaces, so
+/// its lock and its backups go next to it.
+fn password_file_path() -> Result<String, i32> {
+    let filename = get_password_file_path(env::var(ROOSTER_FILE_ENV_VAR), env::home_dir())?;
+    // A password file that doesn't exist yet has no link to follow.
+    match fs::canonicalize(&filename).map(|path| path.into_os_string().into_string()) {
+        Ok(Ok(resolved)) => Ok(resolved),
+        _ => Ok(filename),
+    }
+}
+
 fn ask_master_password() -> IoResult<SafeString> {
     prompt_password_stderr("Type your master password: ").map(SafeString::new)
 }
@@ -268,6 +150,8 @@ fn usage(password_file: &str) {
     println!("");
     println!("The current password file is: {}", password_file);
     println!("You may override this path in the $ROOSTER_FILE environment variable.");
+    println!("Rooster keeps backups of it, set $ROOSTER_BACKUPS to tell how many (0 for none).");
+    println!("Deleted passwords are purged after 30 days, or as many as $ROOSTER_TRASH_DAYS says.");
     println!("");
     println!("Usage:");
     println!("    rooster -h");
@@ -280,19 +164,50 @@ fn usage(password_file: &str) {
     println!("    -a, --alnum       Only use alpha numeric (a-z, A-Z, 0-9) in generated passwords");
     println!("    -l, --length      Set a custom length for the generated password, default is 32");
     println!("    -s, --show        Show the password instead of copying it to the clipboard");
+    println!("    -k, --keyfile     Use a keyfile along with the master password");
+    println!("        --no-keyfile  Stop using a keyfile, when changing the master password");
+    println!("    -f, --field       Set a custom field with NAME=VALUE, or get one with NAME");
+    println!("    -t, --tag         Only list or search the passwords with this tag");
+    println!("        --tree        List passwords as a tree of groups");
+    println!("        --staged      Keep the regenerated password as pending, until confirmed");
+    println!("        --pending     Get the pending password instead of the current one");
+    println!("        --secret-field");
+    println!("                      Set a custom field with NAME, its value is asked for and \
+              only shown on request");
+    println!("        --wait        Wait for other Rooster processes to be done with the \
+              password file");
+    println!("        --allow-expensive-kdf");
+    println!("                      Open password files whose key derivation takes more than 4 \
+              GiB of memory or 64 passes over 1 GiB, or 256 across all key slots");
     println!("");
     println!("Commands:");
+    println!("    init                       Create a new password file");
     println!("    add                        Add a new password manually");
     println!("    change                     Change a password manually");
-    println!("    delete                     Delete a password");
+    println!("    delete                     Move a password to the trash");
+    println!("    restore                    Bring a deleted password back from the trash");
+    println!("    trash                      List or empty the deleted passwords");
     println!("    generate                   Generate a password");
     println!("    regenerate                 Re-generate a previously existing password");
+    println!("    confirm                    Use the pending password of an app from now on");
+    println!("    abort                      Drop the pending password of an app");
     println!("    get                        Retrieve a password");
+    println!("    history                    List and retrieve the previous passwords of an app");
     println!("    rename                     Rename the app for a password");
+    println!("    move                       Move a password or a group of passwords");
     println!("    list                       List all apps and usernames");
     println!("    search                     Search for a specific password");
+    println!("    note                       Add, edit and show secure notes");
+    println!("    tag                        Sort your passwords with tags");
     println!("    export                     Dump all passwords in unencrypted JSON");
     println!("    change-master-password     Change your master password");
+    println!("    tune-kdf                   Make your master password harder to brute force");
+    println!("    slots                      Manage the passwords and recovery keys that open \
+              your password file");
+    println!("    split-recovery             Split a new recovery key between several people");
+    println!("    recover                    Set a new master password with recovery key shares");
+    println!("    backups                    List and restore previous versions of your passwords");
+    println!("    verify                     Check your password file for damage");
 }
 
 fn main() {
@@ -313,6 +228,41 @@ fn main() {
     opts.optflag("s",
                  "show",
                  "Show the password instead of copying it to the clipboard");
+    opts.optopt("k",
+                "keyfile",
+                "Use a keyfile along with the master password",
+                "PATH");
+    opts.optflag("",
+                 "no-keyfile",
+                 "Stop using a keyfile, when changing the master password");
+    opts.optmulti("f",
+                  "field",
+                  "Set a custom field, or get one with its name",
+                  "NAME[=VALUE]");
+    opts.optmulti("t",
+                  "tag",
+                  "Only list or search the passwords with this tag",
+                  "TAG");
+    opts.optflag("",
+                 "tree",
+                 "List passwords as a tree of groups");
+    opts.optflag("",
+                 "staged",
+                 "Keep the regenerated password as pending, until confirmed");
+    opts.optflag("",
+                 "pending",
+                 "Get the pending password instead of the current one");
+    opts.optmulti("",
+                  "secret-field",
+                  "Set a custom field whose value is asked for",
+                  "NAME");
+    opts.optopt("",
+                "wait",
+                "Wait for other Rooster processes to be done with the password file",
+                "SECONDS");
+    opts.optflag("",
+                 "allow-expensive-kdf",
+                 "Open password files whose key derivation takes a lot of memory or time");
 
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
@@ -323,8 +273,7 @@ fn main() {
     };
 
     // Fetch the Rooster file path now, so we can display it in help messages.
-    let password_file_path = match get_password_file_path(env::var(ROOSTER_FILE_ENV_VAR),
-                                                          env::home_dir()) {
+    let password_file_path = match password_file_path() {
         Ok(path) => path,
         Err(_) => {
             println_err!("Woops, I could not determine where your password file is.");
@@ -354,7 +303,20 @@ fn main() {
         }
     };
 
-    let command = match command_from_name(command_name.as_ref()) {
+    // Some commands work on the password file itself, so we don't open it for them.
+    if let Some(command) = commands::file_command_from_name(command_name.as_ref()) {
+        if matches.opt_present("help") {
+            (command.callback_help)();
+            std::process::exit(0);
+        }
+
+        match (command.callback_exec)(&matches, password_file_path.deref()) {
+            Err(i) => std::process::exit(i),
+            _ => std::process::exit(0),
+        }
+    }
+
+    let command = match commands::command_from_name(command_name.as_ref()) {
         Some(command) => command,
         None => {
             println_err!("Woops, the command `{}` does not exist. Try the --help option for more \
@@ -369,7 +331,13 @@ fn main() {
         std::process::exit(0);
     }
 
-    let (new_master_password, mut file) = match get_password_file(password_file_path.deref()) {
+    // Read the keyfile before anything else, so we don't ask for the master password in vain.
+    let keyfile = match commands::read_keyfile_from_matches(&matches) {
+        Ok(keyfile) => keyfile,
+        Err(i) => std::process::exit(i),
+    };
+
+    let (new_master_password, _) = match get_password_file(password_file_path.deref()) {
         Ok(file) => file,
         Err(err) => {
             if format!("{}", err) == DONT_CREATE_PASSWORD_FILE {
@@ -399,7 +367,11 @@ fn main() {
         }
     };
 
-    match execute_command_from_filename(&matches, command, &mut file, master_password) {
+    match commands::execute_command_from_filename(&matches,
+                                        command,
+                                        password_file_path.deref(),
+                                        master_password,
+                                        keyfile) {
         Err(i) => std::process::exit(i),
         _ => std::process::exit(0),
     }
@@ -0,0 +1,474 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::super::crypto::scrypt;
+use super::super::crypto::sha2::Sha512;
+use super::super::crypto::digest::Digest;
+use super::super::argon2;
+use super::super::byteorder::{ReadBytesExt, WriteBytesExt, BigEndian};
+use super::super::safe_vec::SafeVec;
+use super::PasswordError;
+use std::io::{Read, Write, Result as IoResult};
+use std::fs::File;
+use std::path::Path;
+use std::ops::{Deref, DerefMut};
+use std::cmp;
+use std::fmt;
+use std::mem;
+use std::time::{Duration, Instant};
+
+/// Identifier of scrypt in the file header.
+const KDF_ID_SCRYPT: u8 = 1;
+
+/// Identifier of Argon2id in the file header.
+const KDF_ID_ARGON2ID: u8 = 2;
+
+/// Argon2id parameters for new password files.
+///
+/// These are the second recommended option from RFC 9106: 64 MiB of memory,
+/// 3 passes and 4 lanes.
+const ARGON2ID_PARAM_MEMORY_KIB: u32 = 64 * 1024;
+const ARGON2ID_PARAM_TIME: u32 = 3;
+const ARGON2ID_PARAM_LANES: u32 = 4;
+
+/// Upper bound for the memory suggested by `tune`, 1 GiB.
+///
+/// Past that point, a lot of machines would start swapping when opening the
+/// password file, so we add passes instead.
+const TUNE_MAX_MEMORY_KIB: u32 = 1024 * 1024;
+
+/// Upper bound for the memory a key derivation may use, 4 GiB.
+///
+/// The parameters come from the password file, which may have been crafted to make Rooster run
+/// out of memory or spin forever. Like `MAX_WORK_KIB`, this is well above what `tune` picks,
+/// and can be lifted with `--allow-expensive-kdf`.
+const MAX_MEMORY_KIB: u64 = 4 * 1024 * 1024;
+
+/// Upper bound for the memory of a key derivation times the passes over it, 64 passes over
+/// 1 GiB.
+const MAX_WORK_KIB: u64 = 64 * 1024 * 1024;
+
+/// Upper bound for the work of all the key derivations needed to open a file, 4 times
+/// `MAX_WORK_KIB`.
+///
+/// A wrong password is tried against every slot of the file, each with its own key derivation.
+/// A crafted file with 255 slots, each just within `MAX_WORK_KIB`, would otherwise take 255
+/// times as long to tell the password is wrong.
+const MAX_TOTAL_WORK_KIB: u64 = 4 * MAX_WORK_KIB;
+
+/// Checks that key derivations whose work adds up to `total_work_kib` may run one after the
+/// other, see `Kdf::work_kib`. They can't take more than `MAX_TOTAL_WORK_KIB`, unless
+/// `allow_expensive` is set.
+pub fn check_total_work(total_work_kib: u64, allow_expensive: bool) -> Result<(), PasswordError> {
+    if !allow_expensive && total_work_kib > MAX_TOTAL_WORK_KIB {
+        return Err(PasswordError::ExpensiveKdfError);
+    }
+    Ok(())
+}
+
+/// Length of the keyfile hash, SHA-512.
+const KEYFILE_HASH_LEN: usize = 64;
+
+/// Reads a keyfile and hashes it.
+///
+/// Any file can be used as a keyfile. Only its SHA-512 hash is combined with the master
+/// password, so it doesn't matter how large it is.
+pub fn read_keyfile(path: &Path) -> IoResult<SafeVec> {
+    let mut file = File::open(path)?;
+    let mut buffer = [0u8; 4096];
+    let mut hash = Sha512::new();
+    loop {
+        let num_bytes = file.read(&mut buffer)?;
+        if num_bytes == 0 {
+            break;
+        }
+        hash.input(&buffer[..num_bytes]);
+    }
+    for b in buffer.iter_mut() {
+        *b = 0;
+    }
+
+    let mut vec = Vec::<u8>::with_capacity(KEYFILE_HASH_LEN);
+    for _ in 0..KEYFILE_HASH_LEN {
+        vec.push(0u8);
+    }
+    let mut output = SafeVec::new(vec);
+    hash.result(output.deref_mut());
+    Ok(output)
+}
+
+/// The key derivation function used to turn the master password into an
+/// encryption key, along with its parameters.
+///
+/// In the file header, it is stored as a one byte identifier followed by the
+/// parameters of that function:
+/// - scrypt (1):   log2n: u8, r: u32, p: u32, all big endian
+/// - argon2id (2): memory in KiB: u32, time: u32, lanes: u32, all big endian
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kdf {
+    Scrypt {
+        log2_n: u8,
+        r: u32,
+        p: u32,
+    },
+    Argon2id {
+        memory_kib: u32,
+        time: u32,
+        lanes: u32,
+    },
+}
+
+impl Kdf {
+    /// The key derivation function used for new password files.
+    pub fn new() -> Kdf {
+        Kdf::Argon2id {
+            memory_kib: ARGON2ID_PARAM_MEMORY_KIB,
+            time: ARGON2ID_PARAM_TIME,
+            lanes: ARGON2ID_PARAM_LANES,
+        }
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> Result<Kdf, PasswordError> {
+        match reader.read_u8()? {
+            KDF_ID_SCRYPT => {
+                let log2_n = reader.read_u8()?;
+                let r = reader.read_u32::<BigEndian>()?;
+                let p = reader.read_u32::<BigEndian>()?;
+                Ok(Kdf::Scrypt {
+                    log2_n: log2_n,
+                    r: r,
+                    p: p,
+                })
+            }
+            KDF_ID_ARGON2ID => {
+                let memory_kib = reader.read_u32::<BigEndian>()?;
+                let time = reader.read_u32::<BigEndian>()?;
+                let lanes = reader.read_u32::<BigEndian>()?;
+                Ok(Kdf::Argon2id {
+                    memory_kib: memory_kib,
+                    time: time,
+                    lanes: lanes,
+                })
+            }
+            _ => Err(PasswordError::CorruptionError),
+        }
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> IoResult<()> {
+        match *self {
+            Kdf::Scrypt { log2_n, r, p } => {
+                writer.write_u8(KDF_ID_SCRYPT)?;
+                writer.write_u8(log2_n)?;
+                writer.write_u32::<BigEndian>(r)?;
+                writer.write_u32::<BigEndian>(p)?;
+            }
+            Kdf::Argon2id { memory_kib, time, lanes } => {
+                writer.write_u8(KDF_ID_ARGON2ID)?;
+                writer.write_u32::<BigEndian>(memory_kib)?;
+                writer.write_u32::<BigEndian>(time)?;
+                writer.write_u32::<BigEndian>(lanes)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the parameters before a key is derived with them.
+    ///
+    /// Parameters that make no sense, which only a damaged or crafted file can have, are a
+    /// `CorruptionError`. Parameters that would take more memory or time than the bounds above
+    /// are an `ExpensiveKdfError`, unless `allow_expensive` is set.
+    pub fn check(&self, allow_expensive: bool) -> Result<(), PasswordError> {
+        let (memory_kib, passes) = self.cost()?;
+        if !allow_expensive &&
+           (memory_kib > MAX_MEMORY_KIB || memory_kib.saturating_mul(passes) > MAX_WORK_KIB) {
+            return Err(PasswordError::ExpensiveKdfError);
+        }
+        Ok(())
+    }
+
+    /// The memory times the passes of a key derivation, in KiB, which is roughly how long it
+    /// takes. Fails like `check` for parameters that make no sense.
+    pub fn work_kib(&self) -> Result<u64, PasswordError> {
+        let (memory_kib, passes) = self.cost()?;
+        Ok(memory_kib.saturating_mul(passes))
+    }
+
+    /// The memory in KiB and the number of passes of a key derivation, once its parameters are
+    /// known to make sense.
+    fn cost(&self) -> Result<(u64, u64), PasswordError> {
+        let cost = match *self {
+            Kdf::Scrypt { log2_n, r, p } => {
+                // The same checks as `ScryptParams::new`, which panics instead.
+                if log2_n == 0 || log2_n as usize >= mem::size_of::<usize>() * 8 || r == 0 ||
+                   p == 0 || log2_n as u64 >= r as u64 * 16 ||
+                   r as u64 * p as u64 >= 0x40000000 {
+                    return Err(PasswordError::CorruptionError);
+                }
+                // scrypt uses 128 * r bytes for each of its n + p blocks.
+                let memory = (1u64 << log2_n)
+                    .checked_add(p as u64)
+                    .and_then(|blocks| blocks.checked_mul(r as u64 * 128))
+                    .ok_or(PasswordError::CorruptionError)?;
+                (memory / 1024, p as u64)
+            }
+            Kdf::Argon2id { memory_kib, time, lanes } => {
+                // The argon2 crate refuses these too, but only once it's deriving the key.
+                if time == 0 || lanes == 0 || lanes > 0xffffff || memory_kib < 8 * lanes {
+                    return Err(PasswordError::CorruptionError);
+                }
+                (memory_kib as u64, time as u64)
+            }
+        };
+        Ok(cost)
+    }
+
+    /// Measures how long this machine takes to derive a key with these parameters.
+    pub fn measure(&self) -> Result<Duration, PasswordError> {
+        let start = Instant::now();
+        self.derive_key("rooster", None, &[0u8; 32], 32, false)?;
+        Ok(start.elapsed())
+    }
+
+    /// Finds Argon2id parameters that take about `target` to derive a key on this machine.
+    ///
+    /// The parameters are never weaker than those of `Kdf::new`. Memory is increased first,
+    /// since that is what makes Argon2id expensive to attack with dedicated hardware. Once
+    /// memory reaches 1 GiB, passes are added instead, as long as the parameters stay within
+    /// the bounds of `check`.
+    ///
+    /// Returns the parameters along with the measured derivation time.
+    pub fn tune(target: Duration) -> Result<(Kdf, Duration), PasswordError> {
+        let mut memory_kib = ARGON2ID_PARAM_MEMORY_KIB;
+        let mut time = ARGON2ID_PARAM_TIME;
+        let lanes = ARGON2ID_PARAM_LANES;
+
+        let mut elapsed = Kdf::new().measure()?;
+
+        // Doubling memory roughly doubles the derivation time.
+        while elapsed * 2 <= target && memory_kib * 2 <= TUNE_MAX_MEMORY_KIB {
+            memory_kib *= 2;
+            elapsed = Kdf::Argon2id {
+                    memory_kib: memory_kib,
+                    time: time,
+                    lanes: lanes,
+                }
+                .measure()?;
+        }
+
+        // Each pass takes about the same time, so we can tell how many fit in the target.
+        let nanos_per_pass = duration_as_nanos(elapsed) / time as u64;
+        if nanos_per_pass > 0 {
+            let passes = duration_as_nanos(target) / nanos_per_pass;
+            let passes = cmp::min(passes, MAX_WORK_KIB / memory_kib as u64);
+            if passes > time as u64 {
+                time = passes as u32;
+            }
+        }
+
+        let kdf = Kdf::Argon2id {
+            memory_kib: memory_kib,
+            time: time,
+            lanes: lanes,
+        };
+        let elapsed = kdf.measure()?;
+        Ok((kdf, elapsed))
+    }
+
+    /// Derives an encryption key of `key_len` bytes from the password.
+    ///
+    /// If there is a keyfile, its hash is appended to the password, so both are needed to
+    /// derive the key. The parameters are checked first, see `check`.
+    pub fn derive_key(&self,
+                      master_password: &str,
+                      keyfile: Option<&[u8]>,
+                      salt: &[u8],
+                      key_len: usize,
+                      allow_expensive: bool)
+                      -> Result<SafeVec, PasswordError> {
+        self.check(allow_expensive)?;
+
+        // Allocate everything upfront, so no copy of the password is left behind when growing.
+        let mut vec = Vec::<u8>::with_capacity(master_password.len() + KEYFILE_HASH_LEN);
+        vec.extend_from_slice(master_password.as_bytes());
+        if let Some(keyfile) = keyfile {
+            vec.extend_from_slice(keyfile);
+        }
+        let secret = SafeVec::new(vec);
+
+        let mut vec = Vec::<u8>::with_capacity(key_len);
+        for _ in 0..key_len {
+            vec.push(0u8);
+        }
+        let mut output = SafeVec::new(vec);
+
+        match *self {
+            Kdf::Scrypt { log2_n, r, p } => {
+                let scrypt_params = scrypt::ScryptParams::new(log2_n, r, p);
+                scrypt::scrypt(secret.deref(),
+                               salt,
+                               &scrypt_params,
+                               output.deref_mut());
+            }
+            Kdf::Argon2id { memory_kib, time, lanes } => {
+                // Parameters were checked above, but the argon2 crate has its own checks.
+                let params = argon2::Params::new(memory_kib, time, lanes, Some(key_len))
+                    .map_err(|_| PasswordError::CorruptionError)?;
+                argon2::Argon2::new(argon2::Algorithm::Argon2id,
+                                    argon2::Version::V0x13,
+                                    params)
+                    .hash_password_into(secret.deref(), salt, output.deref_mut())
+                    .map_err(|_| PasswordError::CorruptionError)?;
+            }
+        }
+
+        Ok(output)
+    }
+}
+
+impl fmt::Display for Kdf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Kdf::Scrypt { log2_n, r, p } => {
+                write!(f, "scrypt (log2n: {}, r: {}, p: {})", log2_n, r, p)
+            }
+            Kdf::Argon2id { memory_kib, time, lanes } => {
+                write!(f,
+                       "argon2id (memory: {} KiB, time: {}, lanes: {})",
+                       memory_kib,
+                       time,
+                       lanes)
+            }
+        }
+    }
+}
+
+fn duration_as_nanos(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000_000 + duration.subsec_nanos() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks the parameters are refused even when expensive ones are allowed.
+    fn assert_refused(kdf: Kdf) {
+        for &allow_expensive in &[false, true] {
+            match kdf.check(allow_expensive) {
+                Err(PasswordError::CorruptionError) => {}
+                other => panic!("{:?}: unexpected result: {:?}", kdf, other),
+            }
+        }
+        // The key derivation itself must fail the same way, rather than panic.
+        match kdf.derive_key("pw", None, &[0u8; 32], 32, true) {
+            Err(PasswordError::CorruptionError) => {}
+            other => panic!("{:?}: unexpected result: {:?}", kdf, other.map(|_| ())),
+        }
+    }
+
+    /// Checks the parameters are refused, unless expensive ones are allowed.
+    fn assert_too_expensive(kdf: Kdf) {
+        match kdf.check(false) {
+            Err(PasswordError::ExpensiveKdfError) => {}
+            other => panic!("{:?}: unexpected result: {:?}", kdf, other),
+        }
+        match kdf.derive_key("pw", None, &[0u8; 32], 32, false) {
+            Err(PasswordError::ExpensiveKdfError) => {}
+            other => panic!("{:?}: unexpected result: {:?}", kdf, other.map(|_| ())),
+        }
+        assert!(kdf.check(true).is_ok());
+    }
+
+    #[test]
+    fn scrypt_parameters_that_make_no_sense_are_refused() {
+        let scrypt = |log2_n, r, p| {
+            Kdf::Scrypt {
+                log2_n: log2_n,
+                r: r,
+                p: p,
+            }
+        };
+        assert_refused(scrypt(0, 8, 1));
+        assert_refused(scrypt(64, 8, 1));
+        assert_refused(scrypt(255, 255, 1));
+        assert_refused(scrypt(14, 0, 1));
+        assert_refused(scrypt(14, 8, 0));
+        // n must be less than 2^(128 * r / 8).
+        assert_refused(scrypt(16, 1, 1));
+        // r * p must be less than 2^30.
+        assert_refused(scrypt(14, 0x8000, 0x8000));
+        assert_refused(scrypt(14, u32::max_value(), u32::max_value()));
+        // The memory scrypt would need doesn't even fit in 64 bits.
+        assert_refused(scrypt(63, 8, 1));
+    }
+
+    #[test]
+    fn argon2id_parameters_that_make_no_sense_are_refused() {
+        let argon2id = |memory_kib, time, lanes| {
+            Kdf::Argon2id {
+                memory_kib: memory_kib,
+                time: time,
+                lanes: lanes,
+            }
+        };
+        assert_refused(argon2id(64 * 1024, 3, 0));
+        assert_refused(argon2id(64 * 1024, 0, 4));
+        assert_refused(argon2id(64 * 1024, 3, 0x1000000));
+        // Each lane needs at least 8 KiB.
+        assert_refused(argon2id(31, 3, 4));
+    }
+
+    #[test]
+    fn expensive_parameters_are_refused_unless_allowed() {
+        assert_too_expensive(Kdf::Argon2id {
+            memory_kib: MAX_MEMORY_KIB as u32 + 1,
+            time: 1,
+            lanes: 4,
+        });
+        assert_too_expensive(Kdf::Argon2id {
+            memory_kib: u32::max_value(),
+            time: u32::max_value(),
+            lanes: 4,
+        });
+        // Within the memory bound, but too many passes over it.
+        assert_too_expensive(Kdf::Argon2id {
+            memory_kib: 1024 * 1024,
+            time: 65,
+            lanes: 4,
+        });
+        assert_too_expensive(Kdf::Scrypt {
+            log2_n: 40,
+            r: 8,
+            p: 1,
+        });
+
+        assert!(Kdf::new().check(false).is_ok());
+        assert!(Kdf::Argon2id {
+                memory_kib: 1024 * 1024,
+                time: 64,
+                lanes: 4,
+            }
+            .check(false)
+            .is_ok());
+    }
+
+    #[test]
+    fn total_work_is_refused_unless_allowed() {
+        assert!(check_total_work(MAX_TOTAL_WORK_KIB, false).is_ok());
+        match check_total_work(MAX_TOTAL_WORK_KIB + 1, false) {
+            Err(PasswordError::ExpensiveKdfError) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(check_total_work(u64::max_value(), true).is_ok());
+    }
+}
@@ -0,0 +1,396 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Upgrades password files from older formats to the current one.
+//!
+//! Each older format has a migration, which opens files of that format and upgrades their
+//! contents to the next one. An old file walks the chain of migrations until it reaches the
+//! current format. Supporting a new format means adding a migration from the previous one.
+
+use super::super::safe_string::SafeString;
+use super::super::safe_vec::SafeVec;
+use super::super::timestamp::Timestamp;
+use super::kdf::Kdf;
+use super::slot::{self, KeySlot};
+use super::{PasswordError, PasswordStore, v1, v2, v3, v4};
+use std::ops::Deref;
+
+/// The contents of a password file, in one of the formats along the chain.
+enum Contents {
+    V1(Vec<v1::Password>),
+    V2(Vec<v2::Password>),
+    V3(v3::PasswordFile),
+    V4(v4::PasswordStore),
+}
+
+struct Migration {
+    /// The format this migration upgrades from.
+    version: u32,
+    /// Opens a file of this format. Fails if the file has another format, or if the master
+    /// password is wrong.
+    open: fn(&SafeString, Option<&[u8]>, &[u8], bool) -> Result<Contents, PasswordError>,
+    /// Upgrades the contents of a file of this format to the next format.
+    upgrade: fn(Contents, &SafeString, Option<SafeVec>, bool) -> Result<Contents, PasswordError>,
+}
+
+/// The formats Rooster can upgrade from. They are tried in this order, so formats without a
+/// header, that can only be recognized by opening them, come last.
+static MIGRATIONS: &'static [Migration] = &[Migration {
+                                                 version: 3,
+                                                 open: open_v3,
+                                                 upgrade: upgrade_v3_v4,
+                                             },
+                                             Migration {
+                                                 version: 2,
+                                                 open: open_v2,
+                                                 upgrade: upgrade_v2_v3,
+                                             },
+                                             Migration {
+                                                 version: 1,
+                                                 open: open_v1,
+                                                 upgrade: upgrade_v1_v2,
+                                             }];
+
+fn open_v1(master_password: &SafeString,
+           _keyfile: Option<&[u8]>,
+           input: &[u8],
+           _allow_expensive: bool)
+           -> Result<Contents, PasswordError> {
+    Ok(Contents::V1(v1::get_all_passwords(master_password.deref(), input)?))
+}
+
+fn open_v2(master_password: &SafeString,
+           _keyfile: Option<&[u8]>,
+           input: &[u8],
+           allow_expensive: bool)
+           -> Result<Contents, PasswordError> {
+    Ok(Contents::V2(v2::get_all_passwords(master_password.deref(), input, allow_expensive)?))
+}
+
+fn open_v3(master_password: &SafeString,
+           keyfile: Option<&[u8]>,
+           input: &[u8],
+           allow_expensive: bool)
+           -> Result<Contents, PasswordError> {
+    Ok(Contents::V3(v3::open(master_password.deref(), keyfile, input, allow_expensive)?))
+}
+
+fn upgrade_v1_v2(contents: Contents,
+                 _master_password: &SafeString,
+                 _keyfile: Option<SafeVec>,
+                 _allow_expensive: bool)
+                 -> Result<Contents, PasswordError> {
+    let v1_passwords = match contents {
+        Contents::V1(v1_passwords) => v1_passwords,
+        _ => return Err(PasswordError::WrongVersionError),
+    };
+
+    Ok(Contents::V2(v1_passwords.iter()
+        .map(|p| {
+            v2::Password {
+                name: p.name.clone(),
+                username: p.username.clone(),
+                password: p.password.clone(),
+                created_at: p.created_at,
+                updated_at: p.updated_at,
+            }
+        })
+        .collect()))
+}
+
+fn upgrade_v2_v3(contents: Contents,
+                 master_password: &SafeString,
+                 keyfile: Option<SafeVec>,
+                 _allow_expensive: bool)
+                 -> Result<Contents, PasswordError> {
+    let v2_passwords = match contents {
+        Contents::V2(v2_passwords) => v2_passwords,
+        _ => return Err(PasswordError::WrongVersionError),
+    };
+
+    // Version 3 encrypts the passwords with a random key, wrapped in a slot for the master
+    // password.
+    let key = slot::generate_random_key()?;
+    let master_slot = KeySlot::new_password(v4::MASTER_SLOT_LABEL,
+                                            master_password.deref(),
+                                            keyfile.as_ref().map(|k| k.deref()),
+                                            Kdf::new(),
+                                            key.deref(),
+                                            false)?;
+
+    Ok(Contents::V3(v3::PasswordFile {
+        key: key,
+        slots: vec![master_slot],
+        slot: 0,
+        passwords: v2_passwords.iter()
+            .map(|p| {
+                v3::Password {
+                    name: p.name.clone(),
+                    username: p.username.clone(),
+                    password: p.password.clone(),
+                    created_at: p.created_at,
+                    updated_at: p.updated_at,
+                }
+            })
+            .collect(),
+    }))
+}
+
+fn upgrade_v3_v4(contents: Contents,
+                 _master_password: &SafeString,
+                 keyfile: Option<SafeVec>,
+                 allow_expensive: bool)
+                 -> Result<Contents, PasswordError> {
+    let v3_file = match contents {
+        Contents::V3(v3_file) => v3_file,
+        _ => return Err(PasswordError::WrongVersionError),
+    };
+
+    // Only the encoding of the passwords changes, the key and the slots are kept.
+    let v4_passwords = v3_file.passwords
+        .iter()
+        .map(|p| {
+            v4::Password {
+                name: p.name.clone(),
+                username: p.username.clone(),
+                password: p.password.clone(),
+                created_at: p.created_at as Timestamp,
+                updated_at: p.updated_at as Timestamp,
+                fields: Vec::new(),
+                kind: v4::EntryKind::Login,
+                tags: Vec::new(),
+                history: Vec::new(),
+                pending: None,
+            }
+        })
+        .collect();
+
+    Ok(Contents::V4(v4::PasswordStore::from_parts(v3_file.key,
+                                                  v3_file.slots,
+                                                  v3_file.slot,
+                                                  keyfile,
+                                                  allow_expensive,
+                                                  v4_passwords)?))
+}
+
+/// Opens a password file of an older format, and upgrades it to the current one.
+///
+/// Before anything changes, `confirm` is called with the version of the file and the version
+/// it will be upgraded to. If it returns false, this fails with `WrongVersionError`. With a
+/// keyfile, the upgraded file will need it to be opened. `allow_expensive` is passed on to the
+/// format the file is opened with, and kept by the upgraded store.
+///
+/// Only the returned store is upgraded. It's up to the caller to keep a copy of the file and
+/// save the store.
+pub fn upgrade<F: Fn(u32, u32) -> bool>(master_password: SafeString,
+                                        keyfile: Option<SafeVec>,
+                                        input: SafeVec,
+                                        allow_expensive: bool,
+                                        confirm: F)
+                                        -> Result<PasswordStore, PasswordError> {
+    // If no migration can open the file, we tell why the first format that recognized it
+    // could not open it, for instance because the keyfile is missing.
+    let mut first_err = None;
+    let mut opened = None;
+    for migration in MIGRATIONS.iter() {
+        let keyfile = keyfile.as_ref().map(|k| k.deref());
+        match (migration.open)(&master_password, keyfile, input.deref(), allow_expensive) {
+            Ok(contents) => {
+                opened = Some((migration.version, contents));
+                break;
+            }
+            Err(PasswordError::WrongVersionError) => {}
+            Err(err) => {
+                if first_err.is_none() {
+                    first_err = Some(err);
+                }
+            }
+        }
+    }
+    let (old_version, mut contents) = match opened {
+        Some(opened) => opened,
+        None => return Err(first_err.unwrap_or(PasswordError::WrongVersionError)),
+    };
+
+    if !confirm(old_version, v4::VERSION) {
+        return Err(PasswordError::WrongVersionError);
+    }
+
+    let mut version = old_version;
+    loop {
+        contents = match contents {
+            Contents::V4(store) => return Ok(store),
+            contents => {
+                let migration = match MIGRATIONS.iter().find(|m| m.version == version) {
+                    Some(migration) => migration,
+                    None => return Err(PasswordError::WrongVersionError),
+                };
+                (migration.upgrade)(contents, &master_password, keyfile.clone(), allow_expensive)?
+            }
+        };
+        version += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::aes;
+    use super::super::super::byteorder::{BigEndian, WriteBytesExt};
+    use super::super::super::crypto::{hmac, scrypt, sha2};
+    use super::super::super::crypto::digest::Digest;
+    use super::super::super::crypto::mac::Mac;
+    use std::cell::Cell;
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{Read, Write};
+    use std::process;
+
+    const V1_JSON: &'static str = "{\"passwords\":[{\"name\":\"github\",\
+                                   \"domain\":\"github.com\",\"username\":\"alice\",\
+                                   \"password\":\"secret\",\"created_at\":1,\
+                                   \"updated_at\":2}]}";
+
+    const V2_JSON: &'static str = "{\"passwords\":[{\"name\":\"github\",\"username\":\"alice\",\
+                                   \"password\":\"secret\",\"created_at\":1,\
+                                   \"updated_at\":2}]}";
+
+    /// A v1 file: the JSON encrypted with the SHA-256 of the master password, then the IV.
+    fn v1_file(master_password: &str) -> Vec<u8> {
+        let mut key = [0u8; 32];
+        let mut hash = sha2::Sha256::new();
+        hash.input(master_password.as_bytes());
+        hash.result(&mut key);
+
+        let iv = [1u8; 16];
+        let mut file = aes::encrypt(V1_JSON.as_bytes(), &key, &iv).unwrap();
+        file.extend_from_slice(&iv);
+        file
+    }
+
+    /// A v2 file: a header with the scrypt parameters, salt and IV, an HMAC-SHA512 signature of
+    /// all of it, then the JSON encrypted with the key scrypt derives from the master password.
+    fn v2_file(master_password: &str) -> Vec<u8> {
+        let (log2_n, r, p) = (4u8, 8u32, 1u32);
+        let salt = [2u8; 32];
+        let iv = [3u8; 16];
+        let mut key = [0u8; 32];
+        scrypt::scrypt(master_password.as_bytes(),
+                       &salt,
+                       &scrypt::ScryptParams::new(log2_n, r, p),
+                       &mut key);
+
+        let mut header = Vec::new();
+        header.write_u32::<BigEndian>(2).unwrap();
+        header.write_u8(log2_n).unwrap();
+        header.write_u32::<BigEndian>(r).unwrap();
+        header.write_u32::<BigEndian>(p).unwrap();
+        let blob = aes::encrypt(V2_JSON.as_bytes(), &key, &iv).unwrap();
+
+        // The signature covers the header in another order than it's written in.
+        let mut signature = hmac::Hmac::new(sha2::Sha512::new(), &key);
+        signature.input(&header);
+        signature.input(&iv);
+        signature.input(&salt);
+        signature.input(&blob);
+
+        let mut file = header;
+        file.write_all(&salt).unwrap();
+        file.write_all(&iv).unwrap();
+        file.write_all(signature.result().code()).unwrap();
+        file.write_all(&blob).unwrap();
+        file
+    }
+
+    /// Upgrades a file, checking which versions the user is asked about.
+    fn upgrade_file(file: Vec<u8>, master_password: &str, old_version: u32) -> PasswordStore {
+        let asked = Cell::new(false);
+        let store = upgrade(SafeString::new(master_password.to_owned()),
+                            None,
+                            SafeVec::new(file),
+                            false,
+                            |from, to| {
+                                assert_eq!((from, to), (old_version, v4::VERSION));
+                                asked.set(true);
+                                true
+                            })
+            .unwrap();
+        assert!(asked.get());
+        store
+    }
+
+    /// The upgraded store has the password of the old file, and still opens with the same
+    /// master password once saved.
+    fn check_upgraded(store: &PasswordStore, master_password: &str, test_name: &str) {
+        let path = env::temp_dir()
+            .join(format!("rooster-test-{}-{}.rooster", process::id(), test_name));
+        store.sync(&path).unwrap();
+        let mut saved = Vec::new();
+        File::open(&path).unwrap().read_to_end(&mut saved).unwrap();
+        fs::remove_file(&path).unwrap();
+        let reopened = PasswordStore::from_input(SafeString::new(master_password.to_owned()),
+                                                 None,
+                                                 SafeVec::new(saved),
+                                                 false)
+            .unwrap();
+
+        for store in &[store, &reopened] {
+            let passwords = store.get_all_passwords();
+            assert_eq!(passwords.len(), 1);
+            assert_eq!(passwords[0].name, "github");
+            assert_eq!(passwords[0].username, "alice");
+            assert_eq!(passwords[0].password.deref(), "secret");
+            assert_eq!(passwords[0].created_at, 1);
+            assert_eq!(passwords[0].updated_at, 2);
+            assert_eq!(passwords[0].kind, v4::EntryKind::Login);
+            assert_eq!(store.get_kdf(), Some(Kdf::new()));
+        }
+    }
+
+    #[test]
+    fn upgrade_opens_v1_files() {
+        let store = upgrade_file(v1_file("pw"), "pw", 1);
+        check_upgraded(&store, "pw", "upgrade-v1");
+    }
+
+    #[test]
+    fn upgrade_opens_v2_files() {
+        let store = upgrade_file(v2_file("pw"), "pw", 2);
+        check_upgraded(&store, "pw", "upgrade-v2");
+    }
+
+    #[test]
+    fn upgrade_needs_the_master_password() {
+        for file in vec![v1_file("pw"), v2_file("pw")] {
+            assert!(upgrade(SafeString::new("wrong".to_owned()),
+                            None,
+                            SafeVec::new(file),
+                            false,
+                            |_, _| panic!("asked to upgrade a file that didn't open"))
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn upgrade_stops_unless_confirmed() {
+        match upgrade(SafeString::new("pw".to_owned()),
+                      None,
+                      SafeVec::new(v2_file("pw")),
+                      false,
+                      |_, _| false) {
+            Err(PasswordError::WrongVersionError) => {}
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -12,14 +12,21 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod kdf;
+mod migration;
+pub mod slot;
 pub mod v1;
 pub mod v2;
+pub mod v3;
+pub mod v4;
 
-use std::io::{Error as IoError, stdin, Write};
-use std::ops::Deref;
+pub use self::migration::upgrade;
+// The current format, the one password files are upgraded to.
+pub use self::v4::{DeletedPassword, EntryKind, Field, Password, PasswordStore, PendingPassword,
+                  PreviousPassword};
+
+use std::io::Error as IoError;
 use std::convert::From;
-use super::safe_string::SafeString;
-use super::safe_vec::SafeVec;
 
 #[derive(Debug)]
 pub enum PasswordError {
@@ -27,10 +34,21 @@ pub enum PasswordError {
     EncryptionError,
     NoSuchAppError,
     AppExistsError,
+    AmbiguousAppError,
     Io(IoError),
     WrongVersionError,
     InvalidJsonError,
+    InvalidPayloadError,
     CorruptionError,
+    WrongMasterPasswordError,
+    KeyfileRequiredError,
+    NoSuchSlotError,
+    SlotExistsError,
+    SlotInUseError,
+    LastPasswordSlotError,
+    TooManySlotsError,
+    InvalidSlotLabelError,
+    ExpensiveKdfError,
 }
 
 impl From<IoError> for PasswordError {
@@ -38,58 +56,3 @@ impl From<IoError> for PasswordError {
         PasswordError::Io(err)
     }
 }
-
-fn upgrade_v1_v2(v1_passwords: &[v1::Password],
-                 v2_store: &mut v2::PasswordStore)
-                 -> Result<(), PasswordError> {
-    for p in v1_passwords.iter() {
-        let v2_password = v2::Password {
-            name: p.name.clone(),
-            username: p.username.clone(),
-            password: p.password.clone(),
-            created_at: p.created_at,
-            updated_at: p.updated_at,
-        };
-        v2_store.add_password(v2_password)?;
-    }
-
-    Ok(())
-}
-
-pub fn upgrade(master_password: SafeString,
-               input: SafeVec)
-               -> Result<v2::PasswordStore, PasswordError> {
-    // If we can't read v1 passwords, we have a hard error, because we previously tried
-    // to read the passwords as v2. Which failed. That means we can't upgrade.
-    let v1_passwords = v1::get_all_passwords(master_password.deref(), input.deref())?;
-
-    println_stderr!("Your Rooster file has version 1. You need to upgrade to version 2.");
-    println_stderr!("");
-    println_stderr!("WARNING: If in doubt, it could mean you've been hacked. Only");
-    println_stderr!("proceed if you recently upgraded your Rooster installation.");
-    println_stderr!("");
-    println_stderr!("Upgrade to version 2? [y/n]");
-    loop {
-        let mut line = String::new();
-        match stdin().read_line(&mut line) {
-            Ok(_) => {
-                if line.starts_with('y') {
-                    break;
-                } else if line.starts_with('n') {
-                    return Err(PasswordError::WrongVersionError);
-                } else {
-                    println_stderr!("I did not get that. Upgrade from v1 to v2? [y/n]");
-                }
-            }
-            Err(io_err) => {
-                return Err(PasswordError::Io(io_err));
-            }
-        }
-    }
-
-    // Upgrade from v1 to v2 if we could read v1 passwords.
-    let mut v2_store = v2::PasswordStore::new(master_password.clone())?;
-    upgrade_v1_v2(v1_passwords.deref(), &mut v2_store)?;
-
-    Ok(v2_store)
-}
@@ -0,0 +1,486 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::super::aead;
+use super::super::rand::{Rng, OsRng};
+use super::super::byteorder::{ReadBytesExt, WriteBytesExt, BigEndian};
+use super::super::safe_string::SafeString;
+use super::super::safe_vec::SafeVec;
+use super::super::shamir::Share;
+use super::PasswordError;
+use super::kdf::{self, Kdf};
+use std::io::{Read, Write, Cursor, Result as IoResult, Error as IoError,
+              ErrorKind as IoErrorKind};
+use std::ops::{Deref, DerefMut};
+
+/// Identifier of a slot unlocked with a password in the file header.
+const SLOT_KIND_PASSWORD: u8 = 1;
+
+/// Identifier of a slot unlocked with a recovery key in the file header.
+const SLOT_KIND_RECOVERY_KEY: u8 = 2;
+
+/// Slot flag telling that a keyfile is needed, along with the password.
+const FLAG_KEYFILE: u32 = 1;
+
+/// Length of the salt passed to the key derivation function.
+pub const SALT_LEN: usize = 32;
+
+/// Length of a recovery key.
+///
+/// Recovery keys are random, so they are used as is to wrap the data key, without a key
+/// derivation function.
+pub const RECOVERY_KEY_LEN: usize = aead::KEY_LEN;
+
+/// Length of the data key once wrapped, including the authentication tag.
+const WRAPPED_KEY_LEN: usize = aead::KEY_LEN + 16;
+
+/// Number of hex digits between dashes when showing a recovery key or one of its shares.
+const RECOVERY_KEY_GROUP_LEN: usize = 8;
+
+fn generate_random_bytes(len: usize) -> IoResult<SafeVec> {
+    let mut bytes = SafeVec::new(vec![0u8; len]);
+    let mut rng = OsRng::new()?;
+    rng.fill_bytes(bytes.deref_mut());
+    Ok(bytes)
+}
+
+/// Checks that trying a secret against each of these slots won't take too long, before any key
+/// is derived, see `kdf::check_total_work`. Slots that need a keyfile are only counted if there
+/// is one, since they aren't tried otherwise.
+///
+/// On failure, tells the first slot that goes over the bound.
+pub fn check_total_kdf_work(slots: &[KeySlot],
+                            keyfile: Option<&[u8]>,
+                            allow_expensive: bool)
+                            -> Result<(), (usize, PasswordError)> {
+    let mut total_work_kib = 0u64;
+    for (i, slot) in slots.iter().enumerate() {
+        if let SlotKind::Password { kdf, keyfile: needs_keyfile, .. } = slot.kind {
+            if needs_keyfile && keyfile.is_none() {
+                continue;
+            }
+            // Parameters that make no sense are told apart when the slot is tried.
+            if let Ok(work_kib) = kdf.work_kib() {
+                total_work_kib = total_work_kib.saturating_add(work_kib);
+                kdf::check_total_work(total_work_kib, allow_expensive).map_err(|err| (i, err))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Creates a random key, used either as the data key or as a recovery key.
+pub fn generate_random_key() -> IoResult<SafeVec> {
+    generate_random_bytes(aead::KEY_LEN)
+}
+
+fn hex_digit(nibble: u8) -> char {
+    char::from_digit(nibble as u32, 16).unwrap()
+}
+
+// Formats bytes as groups of hex digits, for instance `0a1b2c3d-4e5f...`.
+fn format_hex(bytes: &[u8]) -> SafeString {
+    let mut formatted = String::with_capacity(bytes.len() * 2 +
+                                              bytes.len() * 2 / RECOVERY_KEY_GROUP_LEN);
+    for (i, b) in bytes.iter().enumerate() {
+        if i > 0 && (i * 2) % RECOVERY_KEY_GROUP_LEN == 0 {
+            formatted.push('-');
+        }
+        formatted.push(hex_digit(b >> 4));
+        formatted.push(hex_digit(b & 0x0f));
+    }
+    SafeString::new(formatted)
+}
+
+// Parses exactly `len` bytes formatted by `format_hex`.
+//
+// Dashes and whitespace are ignored, so the bytes can be typed any way the user likes.
+fn parse_hex(formatted: &str, len: usize) -> Option<SafeVec> {
+    let mut bytes = SafeVec::new(vec![0u8; len]);
+    let mut num_digits = 0;
+    for c in formatted.chars().filter(|c| *c != '-' && !c.is_whitespace()) {
+        let digit = match c.to_digit(16) {
+            Some(digit) => digit as u8,
+            None => return None,
+        };
+        if num_digits == len * 2 {
+            return None;
+        }
+        bytes[num_digits / 2] = bytes[num_digits / 2] << 4 | digit;
+        num_digits += 1;
+    }
+    if num_digits == len * 2 {
+        Some(bytes)
+    } else {
+        None
+    }
+}
+
+/// Formats a recovery key so it can be written down.
+pub fn format_recovery_key(recovery_key: &[u8]) -> SafeString {
+    format_hex(recovery_key)
+}
+
+/// Parses a recovery key formatted by `format_recovery_key`.
+pub fn parse_recovery_key(formatted: &str) -> Option<SafeVec> {
+    parse_hex(formatted, RECOVERY_KEY_LEN)
+}
+
+/// Formats a share of a recovery key so it can be written down.
+///
+/// The threshold and the share number come first, so shares can be typed back in any order.
+pub fn format_recovery_key_share(share: &Share) -> SafeString {
+    // Allocate everything upfront, so no copy of the share is left behind when growing.
+    let mut vec = Vec::<u8>::with_capacity(2 + share.y.len());
+    vec.push(share.threshold);
+    vec.push(share.x);
+    vec.extend_from_slice(share.y.deref());
+    let bytes = SafeVec::new(vec);
+    format_hex(bytes.deref())
+}
+
+/// Parses a share formatted by `format_recovery_key_share`.
+pub fn parse_recovery_key_share(formatted: &str) -> Option<Share> {
+    let bytes = match parse_hex(formatted, 2 + RECOVERY_KEY_LEN) {
+        Some(bytes) => bytes,
+        None => return None,
+    };
+    if bytes[1] == 0 {
+        return None;
+    }
+    Some(Share {
+        threshold: bytes[0],
+        x: bytes[1],
+        y: SafeVec::new(bytes[2..].to_vec()),
+    })
+}
+
+/// What unlocks a key slot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlotKind {
+    /// A password, and a keyfile if `keyfile` is true. The key that wraps the data key is
+    /// derived from them with `kdf`.
+    Password {
+        kdf: Kdf,
+        salt: [u8; SALT_LEN],
+        keyfile: bool,
+    },
+    /// A random recovery key, see `generate_random_key`.
+    RecoveryKey,
+}
+
+/// A copy of the data key, wrapped with a key only the holder of the slot's secret can get.
+///
+/// The data key encrypts the passwords. Each slot can unlock it on its own, so there can be
+/// several ways to open the same password file, much like LUKS key slots.
+///
+/// In the file header, a slot is stored as:
+/// - kind:        u8, 1 for a password, 2 for a recovery key
+/// - label:       u8 length followed by as many bytes of UTF-8
+/// - if a password:
+///   - flags:     u32, big endian, 1 if a keyfile is required
+///   - kdf:       u8 identifier followed by its parameters, see `Kdf`
+///   - salt:      256 bits
+/// - nonce:       192 bits
+/// - wrapped key: 384 bits, XChaCha20-Poly1305 with a 128 bits tag
+///
+/// Everything before the nonce is authenticated along with the wrapped key.
+#[derive(Clone, Debug)]
+pub struct KeySlot {
+    label: String,
+    kind: SlotKind,
+    nonce: [u8; aead::NONCE_LEN],
+    wrapped_key: Vec<u8>,
+}
+
+impl KeySlot {
+    /// Creates a slot unlocked with a password, and a keyfile if there is one.
+    ///
+    /// The keyfile is the hash returned by `kdf::read_keyfile`. `allow_expensive` is passed on
+    /// to `Kdf::derive_key`.
+    pub fn new_password(label: &str,
+                        password: &str,
+                        keyfile: Option<&[u8]>,
+                        kdf: Kdf,
+                        data_key: &[u8],
+                        allow_expensive: bool)
+                        -> Result<KeySlot, PasswordError> {
+        let mut salt: [u8; SALT_LEN] = [0u8; SALT_LEN];
+        salt.copy_from_slice(generate_random_bytes(SALT_LEN)?.deref());
+
+        let kind = SlotKind::Password {
+            kdf: kdf,
+            salt: salt,
+            keyfile: keyfile.is_some(),
+        };
+        let wrapping_key =
+            kdf.derive_key(password, keyfile, &salt, aead::KEY_LEN, allow_expensive)?;
+        KeySlot::wrap(label, kind, wrapping_key.deref(), data_key)
+    }
+
+    /// Creates a slot unlocked with a recovery key.
+    pub fn new_recovery_key(label: &str,
+                            recovery_key: &[u8],
+                            data_key: &[u8])
+                            -> Result<KeySlot, PasswordError> {
+        KeySlot::wrap(label, SlotKind::RecoveryKey, recovery_key, data_key)
+    }
+
+    fn wrap(label: &str,
+            kind: SlotKind,
+            wrapping_key: &[u8],
+            data_key: &[u8])
+            -> Result<KeySlot, PasswordError> {
+        if label.len() > u8::max_value() as usize {
+            return Err(PasswordError::InvalidSlotLabelError);
+        }
+
+        let mut nonce: [u8; aead::NONCE_LEN] = [0u8; aead::NONCE_LEN];
+        nonce.copy_from_slice(generate_random_bytes(aead::NONCE_LEN)?.deref());
+
+        let mut slot = KeySlot {
+            label: label.to_owned(),
+            kind: kind,
+            nonce: nonce,
+            wrapped_key: Vec::new(),
+        };
+        slot.wrapped_key = match aead::encrypt(data_key,
+                                               wrapping_key,
+                                               &slot.nonce,
+                                               slot.params()?.deref()) {
+            Ok(wrapped_key) => wrapped_key,
+            Err(_) => return Err(PasswordError::EncryptionError),
+        };
+        Ok(slot)
+    }
+
+    pub fn get_label(&self) -> &str {
+        self.label.deref()
+    }
+
+    pub fn get_kind(&self) -> SlotKind {
+        self.kind
+    }
+
+    /// Unwraps the data key.
+    ///
+    /// The secret is either the password or the formatted recovery key, depending on the kind
+    /// of slot. Returns `None` if the slot can't be tried with what we were given, that is, a
+    /// keyfile is needed but missing, or the secret doesn't look like a recovery key.
+    ///
+    /// Like when the slot was created, `allow_expensive` is passed on to `Kdf::derive_key`.
+    pub fn unlock(&self,
+                  secret: &str,
+                  keyfile: Option<&[u8]>,
+                  allow_expensive: bool)
+                  -> Result<Option<SafeVec>, PasswordError> {
+        let wrapping_key = match self.kind {
+            SlotKind::Password { kdf, salt, keyfile: needs_keyfile } => {
+                let keyfile = if needs_keyfile {
+                    match keyfile {
+                        Some(keyfile) => Some(keyfile),
+                        None => return Ok(None),
+                    }
+                } else {
+                    None
+                };
+                kdf.derive_key(secret, keyfile, &salt, aead::KEY_LEN, allow_expensive)?
+            }
+            SlotKind::RecoveryKey => {
+                match parse_recovery_key(secret) {
+                    Some(recovery_key) => recovery_key,
+                    None => return Ok(None),
+                }
+            }
+        };
+
+        match aead::decrypt(self.wrapped_key.deref(),
+                            wrapping_key.deref(),
+                            &self.nonce,
+                            self.params()?.deref()) {
+            Ok(data_key) => Ok(Some(data_key)),
+            Err(_) => Err(PasswordError::DecryptionError),
+        }
+    }
+
+    /// Serializes the part of the slot that is authenticated with the wrapped key.
+    fn params(&self) -> IoResult<Vec<u8>> {
+        let mut cursor: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        match self.kind {
+            SlotKind::Password { .. } => cursor.write_u8(SLOT_KIND_PASSWORD)?,
+            SlotKind::RecoveryKey => cursor.write_u8(SLOT_KIND_RECOVERY_KEY)?,
+        }
+        cursor.write_u8(self.label.len() as u8)?;
+        cursor.write_all(self.label.as_bytes())?;
+        if let SlotKind::Password { kdf, salt, keyfile } = self.kind {
+            cursor.write_u32::<BigEndian>(if keyfile { FLAG_KEYFILE } else { 0 })?;
+            kdf.write(&mut cursor)?;
+            cursor.write_all(&salt)?;
+        }
+        Ok(cursor.into_inner())
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> Result<KeySlot, PasswordError> {
+        let kind = reader.read_u8()?;
+
+        let label_len = reader.read_u8()? as usize;
+        let mut label = vec![0u8; label_len];
+        read_exact(reader, &mut label)?;
+        let label = String::from_utf8(label).map_err(|_| PasswordError::CorruptionError)?;
+
+        let kind = match kind {
+            SLOT_KIND_PASSWORD => {
+                // Unknown flags mean the file was written by a newer Rooster.
+                let flags = reader.read_u32::<BigEndian>()?;
+                if flags & !FLAG_KEYFILE != 0 {
+                    return Err(PasswordError::WrongVersionError);
+                }
+                let kdf = Kdf::read(reader)?;
+                let mut salt: [u8; SALT_LEN] = [0u8; SALT_LEN];
+                read_exact(reader, &mut salt)?;
+                SlotKind::Password {
+                    kdf: kdf,
+                    salt: salt,
+                    keyfile: flags & FLAG_KEYFILE != 0,
+                }
+            }
+            SLOT_KIND_RECOVERY_KEY => SlotKind::RecoveryKey,
+            _ => return Err(PasswordError::CorruptionError),
+        };
+
+        let mut nonce: [u8; aead::NONCE_LEN] = [0u8; aead::NONCE_LEN];
+        read_exact(reader, &mut nonce)?;
+
+        let mut wrapped_key = vec![0u8; WRAPPED_KEY_LEN];
+        read_exact(reader, &mut wrapped_key)?;
+
+        Ok(KeySlot {
+            label: label,
+            kind: kind,
+            nonce: nonce,
+            wrapped_key: wrapped_key,
+        })
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> IoResult<()> {
+        writer.write_all(self.params()?.deref())?;
+        writer.write_all(&self.nonce)?;
+        writer.write_all(self.wrapped_key.deref())?;
+        Ok(())
+    }
+}
+
+fn read_exact<R: Read>(reader: &mut R, buffer: &mut [u8]) -> IoResult<()> {
+    reader.read(buffer)
+        .and_then(|num_bytes| {
+            if num_bytes == buffer.len() {
+                Ok(())
+            } else {
+                Err(IoError::new(IoErrorKind::Other, "unexpected eof"))
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A slot whose key derivation takes as long as `kdf::check` allows, 64 passes over 1 GiB.
+    fn expensive_slot(label: &str, keyfile: bool) -> KeySlot {
+        KeySlot {
+            label: label.to_owned(),
+            kind: SlotKind::Password {
+                kdf: Kdf::Argon2id {
+                    memory_kib: 1024 * 1024,
+                    time: 64,
+                    lanes: 4,
+                },
+                salt: [0u8; SALT_LEN],
+                keyfile: keyfile,
+            },
+            nonce: [0u8; aead::NONCE_LEN],
+            wrapped_key: vec![0u8; WRAPPED_KEY_LEN],
+        }
+    }
+
+    #[test]
+    fn total_kdf_work_is_bounded() {
+        let mut slots = Vec::new();
+        for i in 0..4 {
+            slots.push(expensive_slot(&format!("slot-{}", i), false));
+            assert!(check_total_kdf_work(slots.deref(), None, false).is_ok());
+        }
+
+        // Each slot is fine on its own, but trying them all would take too long.
+        slots.push(expensive_slot("slot-4", false));
+        if let SlotKind::Password { kdf, .. } = slots[4].kind {
+            assert!(kdf.check(false).is_ok());
+        }
+        match check_total_kdf_work(slots.deref(), None, false) {
+            Err((4, PasswordError::ExpensiveKdfError)) => {}
+            other => panic!("expected slot 4 to be too expensive, got {:?}", other),
+        }
+        assert!(check_total_kdf_work(slots.deref(), None, true).is_ok());
+    }
+
+    #[test]
+    fn total_kdf_work_skips_slots_needing_a_missing_keyfile() {
+        let mut slots = Vec::new();
+        for i in 0..4 {
+            slots.push(expensive_slot(&format!("slot-{}", i), false));
+        }
+        slots.push(expensive_slot("with-keyfile", true));
+
+        assert!(check_total_kdf_work(slots.deref(), None, false).is_ok());
+        assert!(check_total_kdf_work(slots.deref(), Some(&[0u8; 64]), false).is_err());
+    }
+
+    #[test]
+    fn recovery_key_shares_parse_back() {
+        let share = Share {
+            threshold: 3,
+            x: 2,
+            y: SafeVec::new((0..RECOVERY_KEY_LEN as u8).collect()),
+        };
+        let formatted = format_recovery_key_share(&share);
+        assert!(formatted.starts_with("03020001-02030405-"));
+
+        let parsed = parse_recovery_key_share(formatted.deref()).unwrap();
+        assert_eq!(parsed.threshold, 3);
+        assert_eq!(parsed.x, 2);
+        assert_eq!(parsed.y.deref(), share.y.deref());
+
+        // Dashes and spaces can be anywhere, and letters in any case.
+        let retyped = formatted.replace("-", " ").to_uppercase();
+        assert_eq!(parse_recovery_key_share(retyped.as_str()).unwrap().y.deref(),
+                   share.y.deref());
+    }
+
+    #[test]
+    fn invalid_recovery_key_shares_are_rejected() {
+        let share = Share {
+            threshold: 3,
+            x: 0,
+            y: SafeVec::new(vec![7u8; RECOVERY_KEY_LEN]),
+        };
+        // The secret itself is at x = 0, that's not a share.
+        assert!(parse_recovery_key_share(format_recovery_key_share(&share).deref()).is_none());
+
+        let formatted = format_recovery_key_share(&Share { x: 1, ..share });
+        assert!(parse_recovery_key_share(&formatted[..formatted.len() - 1]).is_none());
+        assert!(parse_recovery_key_share(&format!("{}0", formatted.deref())).is_none());
+        assert!(parse_recovery_key_share(&formatted.replace("7", "g")).is_none());
+        assert!(parse_recovery_key_share("").is_none());
+    }
+}
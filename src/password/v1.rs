@@ -12,7 +12,6 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use super::super::ffi;
 use super::super::crypto;
 use super::super::crypto::digest::Digest;
 use super::super::aes;
@@ -30,7 +29,7 @@ use std::ops::Deref;
 
 /// The schema of the JSON content in the password file.
 ///
-/// ```
+/// ```json
 /// {
 ///     "passwords": [
 ///         "name": "YouTube",
@@ -65,8 +64,8 @@ pub struct Password {
     pub domain: Option<String>,
     pub username: String,
     pub password: SafeString,
-    pub created_at: ffi::time_t,
-    pub updated_at: ffi::time_t,
+    pub created_at: u32,
+    pub updated_at: u32,
 }
 
 /// Derives a 256 bits encryption key from the password.
@@ -0,0 +1,282 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::super::aead;
+use super::super::byteorder::{ReadBytesExt, BigEndian};
+use super::super::rustc_serialize::json;
+use super::super::safe_string::SafeString;
+use super::super::safe_vec::SafeVec;
+use super::PasswordError;
+use super::slot::{self, KeySlot};
+use std::io::{Error as IoError, ErrorKind as IoErrorKind, Read, Cursor};
+use std::ops::Deref;
+
+/// The schema of the JSON content in the password file.
+///
+/// ```json
+/// {
+///     "passwords": [
+///         "name": "YouTube",
+///         "username": "conradk",
+///         "password": "xxxxxxxx",
+///         "created_at": 23145436,
+///         "updated_at": 23145546,
+///     ]
+/// }
+/// ```
+
+/// Length of the nonce used for authenticated encryption.
+const NONCE_LEN: usize = aead::NONCE_LEN;
+
+/// The version of this lib
+const VERSION: u32 = 3;
+
+/// The format of the encrypted JSON content in the password file v3.
+#[derive(RustcDecodable, RustcEncodable)]
+pub struct Schema {
+    passwords: Vec<Password>,
+}
+
+#[derive(Clone, Debug, RustcDecodable, RustcEncodable)]
+pub struct Password {
+    pub name: String,
+    pub username: String,
+    pub password: SafeString,
+    pub created_at: u32,
+    pub updated_at: u32,
+}
+
+/// The contents of a Rooster file v3, along with what it takes to write them in the next
+/// format without changing how the file opens.
+pub struct PasswordFile {
+    /// The random key the passwords are encrypted with.
+    pub key: SafeVec,
+    pub slots: Vec<KeySlot>,
+    /// Index of the slot the file was opened with.
+    pub slot: usize,
+    pub passwords: Vec<Password>,
+}
+
+fn unexpected_eof() -> PasswordError {
+    PasswordError::Io(IoError::new(IoErrorKind::Other, "unexpected eof"))
+}
+
+/// Reads a Rooster file v3
+///
+/// The Rooster file has the following format:
+/// - rooster version: u32, big endian
+/// - flags:           u32, big endian, reserved for future use, always 0
+/// - slot count:      u8
+/// - key slots:       variable length, see `KeySlot`
+/// - nonce:           192 bits
+/// - encrypted blob:  variable length, XChaCha20-Poly1305 with a 128 bits tag
+///
+/// Everything before the encrypted blob is the header. It is authenticated
+/// along with the blob, so there is no separate signature.
+///
+/// Like `v4::PasswordStore::open`, the key derivations of the slots are bounded unless
+/// `allow_expensive` is set.
+pub fn open(master_password: &str,
+            keyfile: Option<&[u8]>,
+            input: &[u8],
+            allow_expensive: bool)
+            -> Result<PasswordFile, PasswordError> {
+    let mut reader = Cursor::new(input);
+
+    // Version taken from network byte order (big endian).
+    let version = reader.read_u32::<BigEndian>()?;
+    if version != VERSION {
+        return Err(PasswordError::WrongVersionError);
+    }
+
+    // Flags were never used in this format.
+    let flags = reader.read_u32::<BigEndian>()?;
+    if flags != 0 {
+        return Err(PasswordError::WrongVersionError);
+    }
+
+    // Read the key slots.
+    let num_slots = reader.read_u8()?;
+    let mut slots = Vec::with_capacity(num_slots as usize);
+    for _ in 0..num_slots {
+        slots.push(KeySlot::read(&mut reader)?);
+    }
+
+    // Read the nonce.
+    let mut nonce: [u8; NONCE_LEN] = [0u8; NONCE_LEN];
+    if reader.read(&mut nonce)? != NONCE_LEN {
+        return Err(unexpected_eof());
+    }
+    let header_len = reader.position() as usize;
+
+    slot::check_total_kdf_work(slots.deref(), keyfile, allow_expensive).map_err(|(_, err)| err)?;
+
+    // Find a slot that opens with what we were given, and get the data key out of it.
+    let mut unlocked = None;
+    let mut tried_any_slot = false;
+    for (i, slot) in slots.iter().enumerate() {
+        match slot.unlock(master_password, keyfile, allow_expensive) {
+            Ok(Some(key)) => {
+                unlocked = Some((i, key));
+                break;
+            }
+            Ok(None) => {}
+            Err(PasswordError::DecryptionError) => {
+                tried_any_slot = true;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    let (slot, key) = match unlocked {
+        Some(unlocked) => unlocked,
+        None if !tried_any_slot && keyfile.is_none() => {
+            return Err(PasswordError::KeyfileRequiredError);
+        }
+        None => return Err(PasswordError::DecryptionError),
+    };
+
+    // Decrypt the data. This also checks that neither the header nor the
+    // blob have been tampered with.
+    let decrypted = match aead::decrypt(&input[header_len..],
+                                        key.deref(),
+                                        nonce.as_ref(),
+                                        &input[..header_len]) {
+        Ok(decrypted) => decrypted,
+        Err(_) => return Err(PasswordError::CorruptionError),
+    };
+
+    // Invalid UTF-8 is an error, rather than something to silently replace.
+    let encoded = match String::from_utf8(decrypted.deref().to_owned()) {
+        Ok(encoded) => SafeString::new(encoded),
+        Err(err) => {
+            drop(SafeVec::new(err.into_bytes()));
+            return Err(PasswordError::InvalidJsonError);
+        }
+    };
+    let schema = match json::decode::<Schema>(encoded.deref()) {
+        Ok(schema) => schema,
+        Err(_) => return Err(PasswordError::InvalidJsonError),
+    };
+
+    Ok(PasswordFile {
+        key: key,
+        slots: slots,
+        slot: slot,
+        passwords: schema.passwords,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::byteorder::WriteBytesExt;
+    use super::super::kdf::Kdf;
+    use std::io::Write;
+
+    const JSON: &'static str = "{\"passwords\":[{\"name\":\"github\",\"username\":\"alice\",\
+                                \"password\":\"secret\",\"created_at\":1,\"updated_at\":2}]}";
+
+    fn test_slot(label: &str, key: &[u8]) -> KeySlot {
+        let kdf = Kdf::Argon2id {
+            memory_kib: 8,
+            time: 1,
+            lanes: 1,
+        };
+        KeySlot::new_password(label, "pw", None, kdf, key, false).unwrap()
+    }
+
+    fn write_header(slots: &[KeySlot], nonce: &[u8]) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.write_u32::<BigEndian>(VERSION).unwrap();
+        header.write_u32::<BigEndian>(0).unwrap();
+        header.write_u8(slots.len() as u8).unwrap();
+        for slot in slots.iter() {
+            slot.write(&mut header).unwrap();
+        }
+        header.write_all(nonce).unwrap();
+        header
+    }
+
+    /// A v3 file for "pw", along with the length of its header.
+    fn test_file() -> (Vec<u8>, usize) {
+        let key = slot::generate_random_key().unwrap();
+        let slots = vec![test_slot("master", key.deref())];
+        let mut file = write_header(slots.deref(), &[3u8; NONCE_LEN]);
+        let header_len = file.len();
+        let encrypted = aead::encrypt(JSON.as_bytes(), key.deref(), &[3u8; NONCE_LEN], &file)
+            .unwrap();
+        file.extend_from_slice(encrypted.deref());
+        (file, header_len)
+    }
+
+    #[test]
+    fn open_decrypts_the_passwords() {
+        let (file, _) = test_file();
+        let opened = open("pw", None, file.deref(), false).unwrap();
+        assert_eq!(opened.slot, 0);
+        assert_eq!(opened.passwords.len(), 1);
+        assert_eq!(opened.passwords[0].name, "github");
+        assert_eq!(opened.passwords[0].password.deref(), "secret");
+
+        match open("wrong", None, file.deref(), false) {
+            Err(PasswordError::DecryptionError) => {}
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn open_fails_when_the_encrypted_blob_changes() {
+        let (file, header_len) = test_file();
+        for i in header_len..file.len() {
+            let mut tampered = file.clone();
+            tampered[i] ^= 1;
+            match open("pw", None, tampered.deref(), false) {
+                Err(PasswordError::CorruptionError) => {}
+                other => panic!("byte {}: unexpected result: {:?}", i, other.map(|_| ())),
+            }
+        }
+    }
+
+    #[test]
+    fn open_fails_when_the_header_changes() {
+        let (file, header_len) = test_file();
+
+        // The nonce of the blob is the end of the header.
+        let mut tampered = file.clone();
+        tampered[header_len - 1] ^= 1;
+        match open("pw", None, tampered.deref(), false) {
+            Err(PasswordError::CorruptionError) => {}
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+
+        // A slot added to the header still unlocks, but the blob was sealed without it.
+        let opened = open("pw", None, file.deref(), false).unwrap();
+        let mut slots = opened.slots.clone();
+        slots.push(test_slot("intruder", opened.key.deref()));
+        let mut tampered = write_header(slots.deref(), &[3u8; NONCE_LEN]);
+        tampered.extend_from_slice(&file[header_len..]);
+        match open("pw", None, tampered.deref(), false) {
+            Err(PasswordError::CorruptionError) => {}
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+
+        // The label of a slot is authenticated with its wrapped key.
+        let mut tampered = file.clone();
+        tampered[4 + 4 + 1 + 2] ^= 1;
+        match open("pw", None, tampered.deref(), false) {
+            Err(PasswordError::DecryptionError) => {}
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,2130 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::super::aead;
+use super::super::crypto::util::fixed_time_eq;
+use super::super::rand::{Rng, OsRng};
+use super::super::byteorder::{ReadBytesExt, WriteBytesExt, BigEndian};
+use super::super::safe_string::SafeString;
+use super::super::safe_vec::SafeVec;
+use super::super::timestamp::{self, Timestamp};
+use super::PasswordError;
+use super::kdf::Kdf;
+use super::slot::{self, KeySlot, SlotKind};
+use std::io::{Seek, SeekFrom, Result as IoResult, Error as IoError, ErrorKind as IoErrorKind,
+              Read, Write, Cursor};
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::ops::Deref;
+use std::{cmp, str};
+
+/// The encoding of the decrypted content of the password file.
+///
+/// It has its own version, so it can change without a new file format:
+/// - payload version: u32, big endian
+/// - password count:  u32, big endian
+/// - passwords, each made of:
+///   - name:       u32 length, big endian, then UTF-8 bytes
+///   - username:   u32 length, big endian, then UTF-8 bytes
+///   - password:   u32 length, big endian, then UTF-8 bytes
+///   - created_at: i64, big endian, u32 in payload version 1
+///   - updated_at: i64, big endian, u32 in payload version 1
+///   - field count: u32, big endian, since payload version 3
+///   - fields, each made of:
+///     - name:  u32 length, big endian, then UTF-8 bytes
+///     - value: u32 length, big endian, then UTF-8 bytes
+///     - flags: u8, see `FIELD_SECRET`
+///   - kind:        u8, 0 for a login and 1 for a note, since payload version 4
+///   - tag count:   u32, big endian, since payload version 5
+///   - tags, each a u32 length, big endian, then UTF-8 bytes
+///   - history count: u32, big endian, since payload version 6
+///   - previous passwords, newest first, each made of:
+///     - password:    u32 length, big endian, then UTF-8 bytes
+///     - replaced_at: i64, big endian
+///   - pending:     u8, 1 if there is a pending password, since payload version 8, then:
+///     - password:  u32 length, big endian, then UTF-8 bytes
+///     - staged_at: i64, big endian
+/// - trash count:     u32, big endian, since payload version 7
+/// - deleted passwords, each encoded like a password, then:
+///   - deleted_at: i64, big endian
+/// - padding:         zeros, see `PADDING_POWER_OF_TWO`
+
+/// Length of the nonce used for authenticated encryption.
+const NONCE_LEN: usize = aead::NONCE_LEN;
+
+/// The version of this lib
+pub const VERSION: u32 = 4;
+
+/// The version of the encoding of the decrypted content.
+const PAYLOAD_VERSION: u32 = 8;
+
+/// How many previous passwords each entry keeps.
+pub const HISTORY_LEN: usize = 10;
+
+/// Kinds of entries, as they are encoded.
+const KIND_LOGIN: u8 = 0;
+const KIND_NOTE: u8 = 1;
+
+/// Field flag telling that its value is as sensitive as a password.
+const FIELD_SECRET: u8 = 1;
+
+/// Header flag telling that the payload is padded. The padding policy follows the flags.
+const FLAG_PADDED: u32 = 1;
+
+/// Padding policy: the payload is padded with zeros up to the next power of two, and at
+/// least `MIN_PADDED_LEN`.
+///
+/// Without it, the size of the file gives away how many passwords it holds, and each copy
+/// of the file tells when that changed. Now, it only changes when the size doubles.
+const PADDING_POWER_OF_TWO: u8 = 1;
+
+/// Smallest padded payload, which holds a few dozen passwords.
+const MIN_PADDED_LEN: usize = 4096;
+
+/// Label of the slot created along with the password file.
+pub const MASTER_SLOT_LABEL: &'static str = "master";
+
+/// Maximum number of key slots, since their count is stored on one byte.
+const MAX_SLOTS: usize = 255;
+
+// Create a random nonce.
+fn generate_random_nonce() -> IoResult<[u8; NONCE_LEN]> {
+    let mut bytes: [u8; NONCE_LEN] = [0; NONCE_LEN];
+    let mut rng = OsRng::new()?;
+    rng.fill_bytes(&mut bytes);
+    Ok(bytes)
+}
+
+/// Serializes the file header.
+///
+/// The header is not encrypted, but it is passed as associated data to the
+/// cipher, so any change to it is detected when decrypting.
+fn header(version: u32,
+          flags: u32,
+          padding_policy: u8,
+          slots: &[KeySlot],
+          nonce: &[u8])
+          -> IoResult<Vec<u8>> {
+    let mut cursor: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+    cursor.write_u32::<BigEndian>(version)?;
+    cursor.write_u32::<BigEndian>(flags)?;
+    if flags & FLAG_PADDED != 0 {
+        cursor.write_u8(padding_policy)?;
+    }
+    cursor.write_u8(slots.len() as u8)?;
+    for slot in slots.iter() {
+        slot.write(&mut cursor)?;
+    }
+    cursor.write_all(nonce)?;
+    Ok(cursor.into_inner())
+}
+
+
+/// Why a Rooster file could not be opened, in more detail than `PasswordError`.
+///
+/// Each variant is a stage of opening the file, in the order they happen. `from_input`
+/// boils them down to a `PasswordError`, `rooster verify` shows them as they are.
+#[derive(Debug)]
+pub enum OpenError {
+    /// The file ends before the end of its header.
+    Truncated,
+    /// The file is in another format version, older or newer.
+    WrongVersion(u32),
+    /// The file has flags only a newer Rooster knows about.
+    UnknownFlags(u32),
+    /// The payload is padded with a policy only a newer Rooster knows about.
+    UnknownPaddingPolicy(u8),
+    /// A key slot can't be read, or has parameters that make no sense.
+    InvalidSlot(usize, PasswordError),
+    /// All slots need a keyfile, but there is none.
+    KeyfileRequired,
+    /// No slot opens with the master password and keyfile.
+    NoMatchingSlot,
+    /// A slot gave us the data key, but the header or the encrypted passwords don't
+    /// authenticate with it. The file has been damaged or tampered with.
+    AuthenticationFailed,
+    /// The passwords could be decrypted, but they are written in a newer encoding.
+    UnknownPayloadVersion(u32),
+    /// The passwords could be decrypted, but their encoding is invalid.
+    InvalidPayload,
+}
+
+impl From<OpenError> for PasswordError {
+    fn from(err: OpenError) -> PasswordError {
+        match err {
+            OpenError::Truncated => {
+                PasswordError::Io(IoError::new(IoErrorKind::Other, "unexpected eof"))
+            }
+            OpenError::WrongVersion(_) |
+            OpenError::UnknownFlags(_) |
+            OpenError::UnknownPaddingPolicy(_) |
+            OpenError::UnknownPayloadVersion(_) => PasswordError::WrongVersionError,
+            OpenError::InvalidSlot(_, err) => err,
+            OpenError::KeyfileRequired => PasswordError::KeyfileRequiredError,
+            OpenError::NoMatchingSlot => PasswordError::DecryptionError,
+            OpenError::AuthenticationFailed => PasswordError::CorruptionError,
+            OpenError::InvalidPayload => PasswordError::InvalidPayloadError,
+        }
+    }
+}
+
+/// The unencrypted part of a Rooster file.
+struct Header {
+    /// Whether the payload is padded. Files written before padding was introduced aren't.
+    padded: bool,
+    slots: Vec<KeySlot>,
+    nonce: [u8; NONCE_LEN],
+    /// Where the encrypted blob starts.
+    len: usize,
+}
+
+fn read_header(input: &[u8]) -> Result<Header, OpenError> {
+    let mut reader = Cursor::new(input);
+
+    // Version taken from network byte order (big endian).
+    let version = reader.read_u32::<BigEndian>().map_err(|_| OpenError::Truncated)?;
+    if version != VERSION {
+        return Err(OpenError::WrongVersion(version));
+    }
+
+    // Read the flags. Unknown flags mean the file was written by a newer Rooster.
+    let flags = reader.read_u32::<BigEndian>().map_err(|_| OpenError::Truncated)?;
+    if flags & !FLAG_PADDED != 0 {
+        return Err(OpenError::UnknownFlags(flags));
+    }
+
+    // Read the padding policy, if the payload is padded.
+    let padded = flags & FLAG_PADDED != 0;
+    if padded {
+        let padding_policy = reader.read_u8().map_err(|_| OpenError::Truncated)?;
+        if padding_policy != PADDING_POWER_OF_TWO {
+            return Err(OpenError::UnknownPaddingPolicy(padding_policy));
+        }
+    }
+
+    // Read the key slots.
+    let num_slots = reader.read_u8().map_err(|_| OpenError::Truncated)?;
+    let mut slots = Vec::with_capacity(num_slots as usize);
+    for i in 0..num_slots as usize {
+        match KeySlot::read(&mut reader) {
+            Ok(slot) => slots.push(slot),
+            Err(PasswordError::Io(_)) => return Err(OpenError::Truncated),
+            Err(err) => return Err(OpenError::InvalidSlot(i, err)),
+        }
+    }
+
+    // Read the old nonce.
+    let mut nonce: [u8; NONCE_LEN] = [0u8; NONCE_LEN];
+    match reader.read(&mut nonce) {
+        Ok(NONCE_LEN) => {}
+        _ => return Err(OpenError::Truncated),
+    }
+
+    Ok(Header {
+        padded: padded,
+        slots: slots,
+        nonce: nonce,
+        len: reader.position() as usize,
+    })
+}
+
+/// Decrypts the passwords that follow the header, with the data key.
+fn decrypt_schema(key: &[u8], header: &Header, input: &[u8]) -> Result<Schema, OpenError> {
+    // Decrypt the data. This also checks that neither the header nor the
+    // blob have been tampered with.
+    match aead::decrypt(&input[header.len..],
+                        key,
+                        header.nonce.as_ref(),
+                        &input[..header.len]) {
+        Ok(decrypted) => Schema::decode(decrypted.deref(), header.padded),
+        Err(_) => Err(OpenError::AuthenticationFailed),
+    }
+}
+
+/// The temporary file the store is written to before it replaces the Rooster file.
+///
+/// It has to be in the same directory, since renaming a file only replaces another one
+/// atomically on the same file system.
+fn temporary_path(path: &Path) -> IoResult<PathBuf> {
+    let file_name = match path.file_name() {
+        Some(file_name) => file_name.to_string_lossy().into_owned(),
+        None => return Err(IoError::new(IoErrorKind::Other, "invalid password file path")),
+    };
+    Ok(path.with_file_name(format!(".{}.tmp", file_name)))
+}
+
+/// Writes the temporary file, with the same permissions as the Rooster file if there is one.
+///
+/// A temporary file left over by a Rooster that crashed is removed first, and the new one is
+/// created from scratch. So if anything else is in its place, like a link to another file,
+/// writing fails instead of going through it.
+fn write_temporary_file(path: &Path,
+                        temp_path: &Path,
+                        output: &[u8])
+                        -> Result<(), PasswordError> {
+    if let Err(err) = fs::remove_file(temp_path) {
+        if err.kind() != IoErrorKind::NotFound {
+            return Err(PasswordError::from(err));
+        }
+    }
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(temp_path)?;
+    if let Ok(metadata) = fs::metadata(path) {
+        fs::set_permissions(temp_path, metadata.permissions())?;
+    }
+
+    // Write the header and the encrypted password data.
+    file.write_all(output)?;
+
+    file.sync_all()?;
+
+    // Read the file back, to make sure what is on disk is what we meant to write.
+    let mut written: Vec<u8> = Vec::new();
+    file.seek(SeekFrom::Start(0))?;
+    file.read_to_end(&mut written)?;
+    if written != output {
+        return Err(PasswordError::Io(IoError::new(IoErrorKind::Other,
+                                                  "file contents differ from what was \
+                                                   written")));
+    }
+
+    Ok(())
+}
+
+/// Flushes the directory of the Rooster file, so that a rename in it survives a crash.
+#[cfg(unix)]
+fn sync_directory(path: &Path) -> IoResult<()> {
+    let directory = match path.parent() {
+        Some(directory) if directory != Path::new("") => directory,
+        _ => Path::new("."),
+    };
+    File::open(directory)?.sync_all()
+}
+
+/// Directories can't be opened as files on Windows, renames are flushed by the file system.
+#[cfg(not(unix))]
+fn sync_directory(_path: &Path) -> IoResult<()> {
+    Ok(())
+}
+
+/// Reads a length-prefixed string of the payload, without copying it.
+fn read_str<'a>(reader: &mut Cursor<&'a [u8]>) -> Result<&'a str, OpenError> {
+    let len = reader.read_u32::<BigEndian>().map_err(|_| OpenError::InvalidPayload)? as usize;
+    let input: &'a [u8] = reader.get_ref();
+    let start = reader.position() as usize;
+    if len > input.len() - start {
+        return Err(OpenError::InvalidPayload);
+    }
+    reader.set_position((start + len) as u64);
+    str::from_utf8(&input[start..start + len]).map_err(|_| OpenError::InvalidPayload)
+}
+
+/// Reads a timestamp of the payload. The first version of the payload had 32 bits ones.
+fn read_timestamp(reader: &mut Cursor<&[u8]>, version: u32) -> Result<Timestamp, OpenError> {
+    let timestamp = if version == 1 {
+        reader.read_u32::<BigEndian>().map(|t| t as Timestamp)
+    } else {
+        reader.read_i64::<BigEndian>()
+    };
+    timestamp.map_err(|_| OpenError::InvalidPayload)
+}
+
+/// Whether two app names are the same, without regard to casing.
+fn same_name(a: &str, b: &str) -> bool {
+    a.to_lowercase() == b.to_lowercase()
+}
+
+/// Whether two passwords are for the same account, that is the same app name and username.
+fn same_account(a: &Password, b: &Password) -> bool {
+    same_name(a.name.deref(), b.name.deref()) && same_name(a.username.deref(), b.username.deref())
+}
+
+/// Whether the app name is `group`, or is in it.
+///
+/// App names can be paths, like "work/aws/prod-root". Each part before a slash is a group,
+/// so this one is in the groups "work" and "work/aws". Like app names, groups don't regard
+/// casing.
+fn is_in_group(name: &str, group: &str) -> bool {
+    let group = group.trim_matches('/');
+    if group.is_empty() {
+        return true;
+    }
+    let name = name.to_lowercase();
+    let group = group.to_lowercase();
+    name == group || name.starts_with(&format!("{}/", group))
+}
+
+/// Reads the custom fields of a password.
+fn read_fields(reader: &mut Cursor<&[u8]>) -> Result<Vec<Field>, OpenError> {
+    let count = reader.read_u32::<BigEndian>().map_err(|_| OpenError::InvalidPayload)?;
+    // Each field takes at least 9 bytes.
+    let left = reader.get_ref().len() - reader.position() as usize;
+    let mut fields = Vec::with_capacity(cmp::min(count as usize, left / 9));
+    for _ in 0..count {
+        let name = read_str(reader)?.to_owned();
+        let value = SafeString::new(read_str(reader)?.to_owned());
+        let flags = reader.read_u8().map_err(|_| OpenError::InvalidPayload)?;
+        if flags & !FIELD_SECRET != 0 {
+            return Err(OpenError::InvalidPayload);
+        }
+        fields.push(Field {
+            name: name,
+            value: value,
+            secret: flags & FIELD_SECRET != 0,
+        });
+    }
+    Ok(fields)
+}
+
+/// Reads the tags of a password.
+fn read_tags(reader: &mut Cursor<&[u8]>) -> Result<Vec<String>, OpenError> {
+    let count = reader.read_u32::<BigEndian>().map_err(|_| OpenError::InvalidPayload)?;
+    // Each tag takes at least 4 bytes.
+    let left = reader.get_ref().len() - reader.position() as usize;
+    let mut tags = Vec::with_capacity(cmp::min(count as usize, left / 4));
+    for _ in 0..count {
+        tags.push(read_str(reader)?.to_owned());
+    }
+    Ok(tags)
+}
+
+/// Reads the previous passwords of a password.
+fn read_history(reader: &mut Cursor<&[u8]>) -> Result<Vec<PreviousPassword>, OpenError> {
+    let count = reader.read_u32::<BigEndian>().map_err(|_| OpenError::InvalidPayload)?;
+    // Each previous password takes at least 12 bytes.
+    let left = reader.get_ref().len() - reader.position() as usize;
+    let mut history = Vec::with_capacity(cmp::min(count as usize, left / 12));
+    for _ in 0..count {
+        let password = SafeString::new(read_str(reader)?.to_owned());
+        let replaced_at = reader.read_i64::<BigEndian>().map_err(|_| OpenError::InvalidPayload)?;
+        history.push(PreviousPassword {
+            password: password,
+            replaced_at: replaced_at,
+        });
+    }
+    Ok(history)
+}
+
+/// Reads the pending password of a password, if it has one.
+fn read_pending(reader: &mut Cursor<&[u8]>) -> Result<Option<PendingPassword>, OpenError> {
+    match reader.read_u8().map_err(|_| OpenError::InvalidPayload)? {
+        0 => Ok(None),
+        1 => {
+            let password = SafeString::new(read_str(reader)?.to_owned());
+            let staged_at = reader.read_i64::<BigEndian>().map_err(|_| OpenError::InvalidPayload)?;
+            Ok(Some(PendingPassword {
+                password: password,
+                staged_at: staged_at,
+            }))
+        }
+        _ => Err(OpenError::InvalidPayload),
+    }
+}
+
+fn write_str(output: &mut Vec<u8>, s: &str) -> IoResult<()> {
+    output.write_u32::<BigEndian>(s.len() as u32)?;
+    output.write_all(s.as_bytes())
+}
+
+/// The decrypted content of the password file, see the top of this file for its encoding.
+#[derive(Clone)]
+pub struct Schema {
+    passwords: Vec<Password>,
+    /// Deleted passwords, oldest first.
+    trash: Vec<DeletedPassword>,
+}
+
+/// How many bytes a password takes once encoded.
+fn encoded_len(p: &Password) -> usize {
+    let len = p.fields.iter().fold(12 + p.name.len() + p.username.len() + p.password.len() + 25,
+                                   |len, f| len + 9 + f.name.len() + f.value.len());
+    let len = p.tags.iter().fold(len + 4, |len, t| len + 4 + t.len());
+    let len = p.history.iter().fold(len, |len, h| len + 12 + h.password.len());
+    len + 1 + p.pending.as_ref().map_or(0, |pending| 12 + pending.password.len())
+}
+
+fn write_password(output: &mut Vec<u8>, p: &Password) -> IoResult<()> {
+    write_str(output, p.name.deref())?;
+    write_str(output, p.username.deref())?;
+    write_str(output, p.password.deref())?;
+    output.write_i64::<BigEndian>(p.created_at)?;
+    output.write_i64::<BigEndian>(p.updated_at)?;
+    output.write_u32::<BigEndian>(p.fields.len() as u32)?;
+    for f in p.fields.iter() {
+        write_str(output, f.name.deref())?;
+        write_str(output, f.value.deref())?;
+        output.write_u8(if f.secret { FIELD_SECRET } else { 0 })?;
+    }
+    let kind = match p.kind {
+        EntryKind::Login => KIND_LOGIN,
+        EntryKind::Note => KIND_NOTE,
+    };
+    output.write_u8(kind)?;
+    output.write_u32::<BigEndian>(p.tags.len() as u32)?;
+    for t in p.tags.iter() {
+        write_str(output, t.deref())?;
+    }
+    output.write_u32::<BigEndian>(p.history.len() as u32)?;
+    for h in p.history.iter() {
+        write_str(output, h.password.deref())?;
+        output.write_i64::<BigEndian>(h.replaced_at)?;
+    }
+    match p.pending {
+        Some(ref pending) => {
+            output.write_u8(1)?;
+            write_str(output, pending.password.deref())?;
+            output.write_i64::<BigEndian>(pending.staged_at)?;
+        }
+        None => output.write_u8(0)?,
+    }
+    Ok(())
+}
+
+/// Reads a password, encoded in the given payload version.
+fn read_password(reader: &mut Cursor<&[u8]>, version: u32) -> Result<Password, OpenError> {
+    let name = read_str(reader)?.to_owned();
+    let username = read_str(reader)?.to_owned();
+    let password = SafeString::new(read_str(reader)?.to_owned());
+    let created_at = read_timestamp(reader, version)?;
+    let updated_at = read_timestamp(reader, version)?;
+    let fields = if version >= 3 {
+        read_fields(reader)?
+    } else {
+        Vec::new()
+    };
+    let kind = if version >= 4 {
+        match reader.read_u8().map_err(|_| OpenError::InvalidPayload)? {
+            KIND_LOGIN => EntryKind::Login,
+            KIND_NOTE => EntryKind::Note,
+            _ => return Err(OpenError::InvalidPayload),
+        }
+    } else {
+        EntryKind::Login
+    };
+    let tags = if version >= 5 {
+        read_tags(reader)?
+    } else {
+        Vec::new()
+    };
+    let history = if version >= 6 {
+        read_history(reader)?
+    } else {
+        Vec::new()
+    };
+    let pending = if version >= 8 {
+        read_pending(reader)?
+    } else {
+        None
+    };
+    Ok(Password {
+        name: name,
+        username: username,
+        password: password,
+        created_at: created_at,
+        updated_at: updated_at,
+        fields: fields,
+        kind: kind,
+        tags: tags,
+        history: history,
+        pending: pending,
+    })
+}
+
+impl Schema {
+    fn new() -> Schema {
+        Schema {
+            passwords: Vec::new(),
+            trash: Vec::new(),
+        }
+    }
+
+    /// Encodes the passwords into a buffer that is wiped when dropped, padded with zeros.
+    ///
+    /// The buffer is allocated at its final size up front, so growing it doesn't leave
+    /// copies of the passwords behind. The padding follows what was actually written though,
+    /// so if `encoded_len` falls behind the encoding, the buffer grows but nothing is lost.
+    fn encode(&self) -> IoResult<SafeVec> {
+        let len = self.passwords.iter().fold(12, |len, p| len + encoded_len(p));
+        let len = self.trash.iter().fold(len, |len, d| len + encoded_len(&d.password) + 8);
+
+        // Writing to a Vec never fails, so the buffer always ends up wiped.
+        let mut output = Vec::with_capacity(cmp::max(len.next_power_of_two(), MIN_PADDED_LEN));
+        output.write_u32::<BigEndian>(PAYLOAD_VERSION)?;
+        output.write_u32::<BigEndian>(self.passwords.len() as u32)?;
+        for p in self.passwords.iter() {
+            write_password(&mut output, p)?;
+        }
+        output.write_u32::<BigEndian>(self.trash.len() as u32)?;
+        for d in self.trash.iter() {
+            write_password(&mut output, &d.password)?;
+            output.write_i64::<BigEndian>(d.deleted_at)?;
+        }
+        debug_assert_eq!(output.len(), len, "encoded_len doesn't match the encoding");
+
+        let padded_len = cmp::max(output.len().next_power_of_two(), MIN_PADDED_LEN);
+        output.resize(padded_len, 0);
+        Ok(SafeVec::new(output))
+    }
+
+    /// Decodes the passwords. Each one is copied once, straight into the struct that holds it.
+    ///
+    /// The encoding tells where the passwords end, so the padding is whatever comes after.
+    fn decode(input: &[u8], padded: bool) -> Result<Schema, OpenError> {
+        let mut reader = Cursor::new(input);
+
+        let version = reader.read_u32::<BigEndian>().map_err(|_| OpenError::InvalidPayload)?;
+        if version == 0 || version > PAYLOAD_VERSION {
+            return Err(OpenError::UnknownPayloadVersion(version));
+        }
+
+        let count = reader.read_u32::<BigEndian>().map_err(|_| OpenError::InvalidPayload)?;
+        // Don't trust the count to reserve memory, each password takes at least 20 bytes.
+        let mut passwords = Vec::with_capacity(cmp::min(count as usize, input.len() / 20));
+        for _ in 0..count {
+            passwords.push(read_password(&mut reader, version)?);
+        }
+
+        let mut trash = Vec::new();
+        if version >= 7 {
+            let count = reader.read_u32::<BigEndian>().map_err(|_| OpenError::InvalidPayload)?;
+            trash.reserve(cmp::min(count as usize, input.len() / 28));
+            for _ in 0..count {
+                let password = read_password(&mut reader, version)?;
+                let deleted_at = reader.read_i64::<BigEndian>()
+                    .map_err(|_| OpenError::InvalidPayload)?;
+                trash.push(DeletedPassword {
+                    password: password,
+                    deleted_at: deleted_at,
+                });
+            }
+        }
+
+        let padding = &input[reader.position() as usize..];
+        if (!padded && padding.len() > 0) || padding.iter().any(|b| *b != 0) {
+            return Err(OpenError::InvalidPayload);
+        }
+
+        Ok(Schema {
+            passwords: passwords,
+            trash: trash,
+        })
+    }
+}
+
+/// Anything else worth keeping along with a password, like the URL of the app or an account
+/// number.
+#[derive(Clone, Debug, RustcDecodable, RustcEncodable)]
+pub struct Field {
+    pub name: String,
+    pub value: SafeString,
+    /// Whether the value is as sensitive as a password, like a PIN. Such values are only shown
+    /// when asked for.
+    pub secret: bool,
+}
+
+/// A password an entry had before, kept in case the new one doesn't work out.
+#[derive(Clone, Debug, RustcDecodable, RustcEncodable)]
+pub struct PreviousPassword {
+    pub password: SafeString,
+    /// When it stopped being the password.
+    pub replaced_at: Timestamp,
+}
+
+/// A new password that isn't in use yet, because the app hasn't accepted it so far. It takes
+/// the place of the password once confirmed.
+#[derive(Clone, Debug, RustcDecodable, RustcEncodable)]
+pub struct PendingPassword {
+    pub password: SafeString,
+    /// When it was generated.
+    pub staged_at: Timestamp,
+}
+
+/// A password in the trash, which can be restored until it is purged.
+#[derive(Clone, Debug)]
+pub struct DeletedPassword {
+    pub password: Password,
+    pub deleted_at: Timestamp,
+}
+
+/// What an entry holds.
+#[derive(Clone, Copy, Debug, PartialEq, RustcDecodable, RustcEncodable)]
+pub enum EntryKind {
+    /// A username and its password.
+    Login,
+    /// A secure note, like recovery instructions. Its text is kept as the password, and it has
+    /// no username.
+    Note,
+}
+
+#[derive(Clone, Debug, RustcDecodable, RustcEncodable)]
+pub struct Password {
+    pub name: String,
+    pub username: String,
+    pub password: SafeString,
+    pub created_at: Timestamp,
+    pub updated_at: Timestamp,
+    /// Custom fields, in the order they were added. Their names are unique, without regard to
+    /// casing.
+    pub fields: Vec<Field>,
+    pub kind: EntryKind,
+    /// Tags to sort entries with, like "work" or "personal". They are unique, without regard
+    /// to casing.
+    pub tags: Vec<String>,
+    /// The previous passwords, newest first, at most `HISTORY_LEN` of them.
+    pub history: Vec<PreviousPassword>,
+    /// The next password, while the app hasn't accepted it yet.
+    pub pending: Option<PendingPassword>,
+}
+
+impl Password {
+    pub fn new(name: String, username: String, password: SafeString) -> Password {
+        let timestamp = timestamp::now();
+        Password {
+            name: name,
+            username: username,
+            password: password,
+            created_at: timestamp,
+            updated_at: timestamp,
+            fields: Vec::new(),
+            kind: EntryKind::Login,
+            tags: Vec::new(),
+            history: Vec::new(),
+            pending: None,
+        }
+    }
+
+    /// A secure note, which may span several lines.
+    pub fn new_note(name: String, text: SafeString) -> Password {
+        let mut note = Password::new(name, String::new(), text);
+        note.kind = EntryKind::Note;
+        note
+    }
+
+    /// The app name and the username, like "github:alice". It tells this password apart from
+    /// other accounts for the same app, when typed by the user.
+    ///
+    /// App names and usernames may contain colons too, so another password can have the same
+    /// account, like "a:b" with username "c" and "a" with username "b:c". To tell a password
+    /// apart for sure, use its app name and username, like `change_password` does.
+    pub fn account(&self) -> String {
+        format!("{}:{}", self.name, self.username)
+    }
+
+    /// Whether this is the password for an app name, or for an account like "github:alice".
+    ///
+    /// Several passwords can match the same name, see `account`.
+    pub fn is_account(&self, name: &str) -> bool {
+        same_name(self.name.deref(), name) || same_name(self.account().deref(), name)
+    }
+
+    pub fn is_note(&self) -> bool {
+        self.kind == EntryKind::Note
+    }
+
+    pub fn get_field(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|f| f.name.to_lowercase() == name.to_lowercase())
+    }
+
+    /// Sets a field. If there is one by that name already, its value is replaced and it keeps
+    /// its place.
+    pub fn set_field(&mut self, field: Field) {
+        let name = field.name.to_lowercase();
+        match self.fields.iter().position(|f| f.name.to_lowercase() == name) {
+            Some(i) => self.fields[i] = field,
+            None => self.fields.push(field),
+        }
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t.to_lowercase() == tag.to_lowercase())
+    }
+
+    /// Whether the password has all of these tags, which is how `--tag` filters passwords.
+    pub fn has_tags(&self, tags: &[String]) -> bool {
+        tags.iter().all(|tag| self.has_tag(tag))
+    }
+
+    /// Tags the password. Returns false if it already had this tag.
+    pub fn add_tag(&mut self, tag: &str) -> bool {
+        if self.has_tag(tag) {
+            return false;
+        }
+        self.tags.push(tag.to_owned());
+        true
+    }
+
+    /// Makes the pending password the current one, see `PendingPassword`. Returns false if
+    /// there was none.
+    pub fn confirm_pending(&mut self) -> bool {
+        match self.pending.take() {
+            Some(pending) => {
+                self.password = pending.password;
+                self.updated_at = timestamp::now();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes a tag. Returns false if the password didn't have it.
+    pub fn remove_tag(&mut self, tag: &str) -> bool {
+        let len = self.tags.len();
+        self.tags.retain(|t| t.to_lowercase() != tag.to_lowercase());
+        self.tags.len() != len
+    }
+
+    /// Removes a field. Returns it, if there was one by that name.
+    pub fn remove_field(&mut self, name: &str) -> Option<Field> {
+        let name = name.to_lowercase();
+        self.fields
+            .iter()
+            .position(|f| f.name.to_lowercase() == name)
+            .map(|i| self.fields.remove(i))
+    }
+}
+
+pub struct PasswordStore {
+    /// The random key the passwords are encrypted with. Each slot holds a wrapped copy.
+    key: SafeVec,
+    slots: Vec<KeySlot>,
+    /// Index of the slot the store was opened with.
+    slot: usize,
+    keyfile: Option<SafeVec>,
+    /// Whether key derivations above the bounds of `Kdf::check` are allowed. The store was
+    /// opened with them allowed, so the slots it creates or checks may need them too.
+    allow_expensive: bool,
+    schema: Schema,
+    /// Whether the store has changed since it was opened, and needs to be saved.
+    modified: bool,
+    /// The master password, if it changed since the store was opened. Once saved, the file is
+    /// opened back with it.
+    new_master_password: Option<SafeString>,
+}
+
+/// Read and writes to a Rooster file
+///
+/// The Rooster file has the following format:
+/// - rooster version: u32, big endian
+/// - flags:           u32, big endian, `FLAG_PADDED` or 0
+/// - padding policy:  u8, only if the payload is padded
+/// - slot count:      u8
+/// - key slots:       variable length, see `KeySlot`
+/// - nonce:           192 bits
+/// - encrypted blob:  variable length, XChaCha20-Poly1305 with a 128 bits tag
+///
+/// Everything before the encrypted blob is the header. It is authenticated
+/// along with the blob, so there is no separate signature. The blob is the
+/// payload, see the top of this file.
+impl PasswordStore {
+    /// Creates an empty store, with a single slot for the master password.
+    ///
+    /// The keyfile is the hash returned by `kdf::read_keyfile`. If there is one, the file
+    /// can only be opened with both the master password and that keyfile.
+    pub fn new(master_password: SafeString,
+               keyfile: Option<SafeVec>)
+               -> Result<PasswordStore, PasswordError> {
+        let key = slot::generate_random_key()?;
+
+        let master_slot = KeySlot::new_password(MASTER_SLOT_LABEL,
+                                                master_password.deref(),
+                                                keyfile.as_ref().map(|k| k.deref()),
+                                                Kdf::new(),
+                                                key.deref(),
+                                                false)?;
+
+        Ok(PasswordStore {
+            key: key,
+            slots: vec![master_slot],
+            slot: 0,
+            keyfile: keyfile,
+            allow_expensive: false,
+            schema: Schema::new(),
+            modified: true,
+            new_master_password: None,
+        })
+    }
+
+    /// Makes a store out of a file of an older format, opened with its data key and slot.
+    ///
+    /// The slots are kept as they are, so the store opens the same ways the file did. `slot` is
+    /// the index of the one it was opened with, so this fails with `NoSuchSlotError` if there
+    /// is no such slot, and with `TooManySlotsError` if the file couldn't hold them all. A data
+    /// key of the wrong length is a `CorruptionError`.
+    pub fn from_parts(key: SafeVec,
+                      slots: Vec<KeySlot>,
+                      slot: usize,
+                      keyfile: Option<SafeVec>,
+                      allow_expensive: bool,
+                      passwords: Vec<Password>)
+                      -> Result<PasswordStore, PasswordError> {
+        if key.len() != aead::KEY_LEN {
+            return Err(PasswordError::CorruptionError);
+        }
+        if slot >= slots.len() {
+            return Err(PasswordError::NoSuchSlotError);
+        }
+        if slots.len() > MAX_SLOTS {
+            return Err(PasswordError::TooManySlotsError);
+        }
+
+        Ok(PasswordStore {
+            key: key,
+            slots: slots,
+            slot: slot,
+            keyfile: keyfile,
+            allow_expensive: allow_expensive,
+            schema: Schema {
+                passwords: passwords,
+                trash: Vec::new(),
+            },
+            modified: true,
+            new_master_password: None,
+        })
+    }
+
+    /// Opens a store from the contents of a Rooster file.
+    ///
+    /// Each slot is tried in turn, so the master password can be any slot's password or a
+    /// recovery key. The keyfile is ignored by slots that don't require one.
+    ///
+    /// Key derivations above the bounds of `Kdf::check` fail with `ExpensiveKdfError`, unless
+    /// `allow_expensive` is set. They can only come from a file that was tampered with, or
+    /// made with such parameters on purpose.
+    pub fn from_input(master_password: SafeString,
+                      keyfile: Option<SafeVec>,
+                      input: SafeVec,
+                      allow_expensive: bool)
+                      -> Result<PasswordStore, PasswordError> {
+        PasswordStore::open(master_password, keyfile, input.deref(), allow_expensive)
+            .map_err(PasswordError::from)
+    }
+
+    /// Opens a store like `from_input`, but tells exactly at which stage it failed.
+    pub fn open(master_password: SafeString,
+                keyfile: Option<SafeVec>,
+                input: &[u8],
+                allow_expensive: bool)
+                -> Result<PasswordStore, OpenError> {
+        let header = read_header(input)?;
+        slot::check_total_kdf_work(header.slots.deref(),
+                                   keyfile.as_ref().map(|k| k.deref()),
+                                   allow_expensive)
+            .map_err(|(i, err)| OpenError::InvalidSlot(i, err))?;
+
+        // Find a slot that opens with what we were given, and get the data key out of it.
+        let mut unlocked = None;
+        let mut tried_any_slot = false;
+        for (i, slot) in header.slots.iter().enumerate() {
+            match slot.unlock(master_password.deref(),
+                              keyfile.as_ref().map(|k| k.deref()),
+                              allow_expensive) {
+                Ok(Some(key)) => {
+                    unlocked = Some((i, key));
+                    break;
+                }
+                Ok(None) => {}
+                Err(PasswordError::DecryptionError) => {
+                    tried_any_slot = true;
+                }
+                Err(err) => {
+                    return Err(OpenError::InvalidSlot(i, err));
+                }
+            }
+        }
+        let (slot, key) = match unlocked {
+            Some(unlocked) => unlocked,
+            // If all slots need a keyfile, that's surely what is missing.
+            None if !tried_any_slot && keyfile.is_none() => {
+                return Err(OpenError::KeyfileRequired);
+            }
+            None => return Err(OpenError::NoMatchingSlot),
+        };
+
+        let schema = decrypt_schema(key.deref(), &header, input)?;
+
+        Ok(PasswordStore {
+            key: key,
+            slots: header.slots,
+            slot: slot,
+            keyfile: keyfile,
+            allow_expensive: allow_expensive,
+            schema: schema,
+            modified: false,
+            new_master_password: None,
+        })
+    }
+
+    /// Encrypts the store into the bytes that make up a Rooster file.
+    fn encrypt(&self) -> Result<Vec<u8>, PasswordError> {
+        let payload = self.schema.encode()?;
+
+        // Encrypt the data with a new nonce, authenticating the header along the way.
+        let nonce = generate_random_nonce()?;
+        let mut output = header(VERSION,
+                                FLAG_PADDED,
+                                PADDING_POWER_OF_TWO,
+                                self.slots.deref(),
+                                &nonce)?;
+        let encrypted = match aead::encrypt(payload.deref(),
+                                            self.key.as_ref(),
+                                            nonce.as_ref(),
+                                            output.deref()) {
+            Ok(val) => val,
+            Err(_) => return Err(PasswordError::EncryptionError),
+        };
+
+        // The header (version, flags, padding policy, key slots and nonce) comes before the
+        // encrypted data.
+        output.extend_from_slice(encrypted.deref());
+        Ok(output)
+    }
+
+    /// Saves the store to the Rooster file at `path`.
+    ///
+    /// The store is written to a temporary file next to it, which then replaces the Rooster
+    /// file in one go. If anything goes wrong before that, the Rooster file is left untouched.
+    ///
+    /// If the Rooster file is a symbolic link, the file it leads to is replaced, and the link
+    /// is left as it is.
+    ///
+    /// If the master password changed, the saved file is then read back and opened with it.
+    /// Should that fail, this returns `CorruptionError`, though the file has been replaced.
+    pub fn sync(&self, path: &Path) -> Result<(), PasswordError> {
+        let output = self.encrypt()?;
+
+        // A file that doesn't exist yet has no link to follow.
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+
+        let temp_path = temporary_path(path.deref())?;
+        let written = write_temporary_file(path.deref(), temp_path.deref(), output.deref())
+            .and_then(|_| {
+                fs::rename(temp_path.deref(), path.deref()).map_err(PasswordError::from)
+            });
+        if written.is_err() {
+            let _ = fs::remove_file(temp_path.deref());
+            return written;
+        }
+
+        // Make sure the rename itself is on disk.
+        sync_directory(path.deref())?;
+
+        if let Some(ref master_password) = self.new_master_password {
+            self.check_saved_file(path.deref(), master_password)?;
+        }
+
+        Ok(())
+    }
+
+    /// Opens the Rooster file at `path` with the master password and the keyfile of this
+    /// store, and checks that it holds the same passwords with the same data key.
+    fn check_saved_file(&self,
+                        path: &Path,
+                        master_password: &SafeString)
+                        -> Result<(), PasswordError> {
+        let mut input = Vec::new();
+        File::open(path)?.read_to_end(&mut input)?;
+        let saved = PasswordStore::from_input(master_password.clone(),
+                                              self.keyfile.clone(),
+                                              SafeVec::new(input),
+                                              self.allow_expensive)
+            .map_err(|_| PasswordError::CorruptionError)?;
+        if !fixed_time_eq(saved.key.deref(), self.key.deref()) ||
+           saved.schema.passwords.len() != self.schema.passwords.len() {
+            return Err(PasswordError::CorruptionError);
+        }
+        Ok(())
+    }
+
+    /// Whether the master password changed since the store was opened.
+    pub fn is_master_password_changed(&self) -> bool {
+        self.new_master_password.is_some()
+    }
+
+    pub fn get_all_passwords(&self) -> &[Password] {
+        self.schema.passwords.deref()
+    }
+
+    /// Adds a password to the file. There can be several accounts for the same app, as long as
+    /// their usernames differ.
+    pub fn add_password(&mut self, password: Password) -> Result<(), PasswordError> {
+        if self.has_account(password.name.deref(), password.username.deref()) {
+            return Err(PasswordError::AppExistsError);
+        }
+        self.schema.passwords.push(password);
+        self.modified = true;
+        Ok(())
+    }
+
+    /// Moves the password for an app name and a username to the trash. It can be restored
+    /// until the trash is purged.
+    pub fn delete_password(&mut self,
+                           name: &str,
+                           username: &str)
+                           -> Result<Password, PasswordError> {
+        let i = self.find_account(name, username)?;
+        let p = self.schema.passwords.remove(i);
+        self.schema.trash.push(DeletedPassword {
+            password: p.clone(),
+            deleted_at: timestamp::now(),
+        });
+        self.modified = true;
+        Ok(p)
+    }
+
+    /// The deleted passwords, oldest first.
+    pub fn get_trash(&self) -> &[DeletedPassword] {
+        self.schema.trash.deref()
+    }
+
+    /// Brings a deleted password back, see `get_accounts`. If it was deleted several times, the
+    /// last one comes back.
+    pub fn restore_password(&mut self, name: &str) -> Result<Password, PasswordError> {
+        let i = match self.schema.trash.iter().rposition(|d| d.password.is_account(name)) {
+            Some(i) => i,
+            None => return Err(PasswordError::NoSuchAppError),
+        };
+        let p = self.schema.trash[i].password.clone();
+        let ambiguous = self.schema
+            .trash
+            .iter()
+            .any(|d| d.password.is_account(name) && !same_account(&d.password, &p));
+        if ambiguous {
+            return Err(PasswordError::AmbiguousAppError);
+        }
+        if self.has_account(p.name.deref(), p.username.deref()) {
+            return Err(PasswordError::AppExistsError);
+        }
+
+        self.schema.trash.remove(i);
+        self.schema.passwords.push(p.clone());
+        self.modified = true;
+        Ok(p)
+    }
+
+    /// Removes the passwords deleted before `deleted_before` from the trash for good. Returns
+    /// how many were removed.
+    pub fn purge_trash(&mut self, deleted_before: Timestamp) -> usize {
+        let len = self.schema.trash.len();
+        self.schema.trash.retain(|d| d.deleted_at >= deleted_before);
+        let purged = len - self.schema.trash.len();
+        if purged > 0 {
+            self.modified = true;
+        }
+        purged
+    }
+
+    /// Searches passwords by app name, and keeps those that have all of the given tags.
+    pub fn search_passwords(&self, name: &str, tags: &[String]) -> Vec<&Password> {
+        // Fuzzy search password app names.
+        let mut keys = self.schema
+            .passwords
+            .iter()
+            .map(|p| p.name.to_lowercase())
+            .collect::<Vec<String>>();
+        keys.sort();
+
+        let mut search_results = vec![];
+        // Check if each app name can be matched against the search query.
+        //
+        // It's fine if there are some characters left out in the query. For instance, you can
+        // search for the app "Facebook" with just "fcbk".
+        for app_name in keys.iter().map(|s| s.as_str()) {
+            let mut matches_query = true;
+            let mut last_i = 0;
+            for c in name.chars() {
+                match app_name[last_i..].find(c.to_lowercase().next().unwrap()) {
+                    // Query chars must be present in the app name in the right order.
+                    Some(ic) => {
+                        last_i += ic + 1;
+                    }
+                    // Query char is not present, no match.
+                    None => {
+                        matches_query = false;
+                        break;
+                    }
+                }
+            }
+
+            if matches_query {
+                search_results.push(app_name.to_owned());
+            }
+        }
+
+        let mut passwords = vec![];
+        for p in self.schema.passwords.iter() {
+            if search_results.contains(&p.name.to_lowercase()) && p.has_tags(tags) {
+                passwords.push(p);
+            }
+        }
+
+        passwords
+    }
+
+    /// The passwords for an app name, or for "app:username" to tell accounts for the same app
+    /// apart.
+    pub fn get_accounts(&self, name: &str) -> Vec<&Password> {
+        self.schema.passwords.iter().filter(|p| p.is_account(name)).collect()
+    }
+
+    /// Finds the index of the only password for an app name, see `get_accounts`.
+    fn find_password(&self, name: &str) -> Result<usize, PasswordError> {
+        let mut found = self.schema
+            .passwords
+            .iter()
+            .enumerate()
+            .filter(|&(_, p)| p.is_account(name))
+            .map(|(i, _)| i);
+        match (found.next(), found.next()) {
+            (Some(i), None) => Ok(i),
+            (Some(_), Some(_)) => Err(PasswordError::AmbiguousAppError),
+            (None, _) => Err(PasswordError::NoSuchAppError),
+        }
+    }
+
+    /// Finds the index of the password for an app name and a username. There can only be one,
+    /// see `add_password`.
+    fn find_account(&self, name: &str, username: &str) -> Result<usize, PasswordError> {
+        self.schema
+            .passwords
+            .iter()
+            .position(|p| {
+                same_name(p.name.deref(), name) && same_name(p.username.deref(), username)
+            })
+            .ok_or(PasswordError::NoSuchAppError)
+    }
+
+    /// The password for an app name, see `get_accounts`. This is `None` if there are several
+    /// accounts for the app.
+    pub fn get_password(&self, name: &str) -> Option<Password> {
+        self.find_password(name).ok().map(|i| self.schema.passwords[i].clone())
+    }
+
+    pub fn has_password(&self, name: &str) -> bool {
+        !self.get_accounts(name).is_empty()
+    }
+
+    /// Whether there is a password for this app and this username, without regard to casing.
+    pub fn has_account(&self, name: &str, username: &str) -> bool {
+        self.find_account(name, username).is_ok()
+    }
+
+    /// The passwords in a group, see `is_in_group`. All of them if the group is empty.
+    pub fn get_passwords_in_group(&self, group: &str) -> Vec<&Password> {
+        self.schema.passwords.iter().filter(|p| is_in_group(p.name.deref(), group)).collect()
+    }
+
+    /// Moves a password or a whole group: each app name that is `from`, or is in the group
+    /// `from`, starts with `to` instead.
+    ///
+    /// Nothing moves if one of the new app names is taken already. Returns how many passwords
+    /// moved.
+    pub fn move_passwords(&mut self, from: &str, to: &str) -> Result<usize, PasswordError> {
+        let from = from.trim_matches('/');
+        let to = to.trim_matches('/');
+        if from.is_empty() || to.is_empty() {
+            return Err(PasswordError::NoSuchAppError);
+        }
+
+        let moves: Vec<(usize, String)> = self.schema
+            .passwords
+            .iter()
+            .enumerate()
+            .filter(|&(_, p)| is_in_group(p.name.deref(), from))
+            .map(|(i, p)| {
+                let rest: String = p.name.chars().skip(from.chars().count()).collect();
+                (i, format!("{}{}", to, rest))
+            })
+            .collect();
+        if moves.is_empty() {
+            return Err(PasswordError::NoSuchAppError);
+        }
+
+        for &(i, ref new_name) in moves.iter() {
+            let username = self.schema.passwords[i].username.deref();
+            let taken = self.schema.passwords.iter().any(|p| {
+                !is_in_group(p.name.deref(), from) && same_name(p.name.deref(), new_name) &&
+                same_name(p.username.deref(), username)
+            });
+            if taken {
+                return Err(PasswordError::AppExistsError);
+            }
+        }
+
+        let now = timestamp::now();
+        for (i, new_name) in moves.iter().cloned() {
+            self.schema.passwords[i].name = new_name;
+            self.schema.passwords[i].updated_at = now;
+        }
+        self.modified = true;
+        Ok(moves.len())
+    }
+
+    /// Changes the password for an app name and a username. It keeps its place.
+    ///
+    /// If the password itself changes, the old one goes in the history. Notes have no history,
+    /// their earlier texts are not kept.
+    pub fn change_password(&mut self,
+                           name: &str,
+                           username: &str,
+                           closure: &Fn(Password) -> Password)
+                           -> Result<(), PasswordError> {
+        let i = self.find_account(name, username)?;
+        let old_p = self.schema.passwords[i].clone();
+        let mut new_p = closure(old_p.clone());
+
+        if !new_p.is_note() && new_p.password.deref() != old_p.password.deref() {
+            new_p.history.insert(0,
+                                 PreviousPassword {
+                                     password: old_p.password.clone(),
+                                     replaced_at: timestamp::now(),
+                                 });
+            new_p.history.truncate(HISTORY_LEN);
+        }
+
+        let taken = self.schema
+            .passwords
+            .iter()
+            .enumerate()
+            .any(|(j, p)| j != i && same_account(p, &new_p));
+        if taken {
+            return Err(PasswordError::AppExistsError);
+        }
+
+        self.schema.passwords[i] = new_p;
+        self.modified = true;
+        Ok(())
+    }
+
+    /// The key derivation function of the slot the store was opened with.
+    ///
+    /// This is `None` if the store was opened with a recovery key.
+    pub fn get_kdf(&self) -> Option<Kdf> {
+        match self.slots[self.slot].get_kind() {
+            SlotKind::Password { kdf, .. } => Some(kdf),
+            SlotKind::RecoveryKey => None,
+        }
+    }
+
+    /// Checks that the master password opens the slot the store was opened with.
+    ///
+    /// Otherwise, we would wrap the data key with a key nobody knows how to derive.
+    fn check_master_password(&self, master_password: &str) -> Result<(), PasswordError> {
+        let keyfile = self.keyfile.as_ref().map(|k| k.deref());
+        match self.slots[self.slot].unlock(master_password, keyfile, self.allow_expensive) {
+            Ok(Some(ref key)) if fixed_time_eq(key.deref(), self.key.deref()) => Ok(()),
+            Ok(_) |
+            Err(PasswordError::DecryptionError) => Err(PasswordError::WrongMasterPasswordError),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Re-wraps the data key of the slot the store was opened with, using a fresh salt and
+    /// another key derivation function.
+    ///
+    /// The master password must be the one the store was opened with.
+    pub fn change_kdf(&mut self, master_password: &str, kdf: Kdf) -> Result<(), PasswordError> {
+        if self.get_kdf().is_none() {
+            return Err(PasswordError::WrongMasterPasswordError);
+        }
+        self.check_master_password(master_password)?;
+
+        let slot = KeySlot::new_password(self.slots[self.slot].get_label(),
+                                         master_password,
+                                         self.keyfile.as_ref().map(|k| k.deref()),
+                                         kdf,
+                                         self.key.deref(),
+                                         self.allow_expensive)?;
+        self.slots[self.slot] = slot;
+        self.modified = true;
+        Ok(())
+    }
+
+    /// The slot whose password is the master password.
+    ///
+    /// That's the slot the store was opened with, unless it was opened with a recovery key.
+    /// Then, it's the first password slot.
+    fn master_slot(&self) -> usize {
+        if self.get_kdf().is_some() {
+            return self.slot;
+        }
+        self.slots
+            .iter()
+            .position(|slot| slot.get_kind() != SlotKind::RecoveryKey)
+            .unwrap_or(self.slot)
+    }
+
+    /// Changes the master password and keyfile.
+    ///
+    /// The master password slot is re-created with a fresh salt and, if given, another key
+    /// derivation function. The other slots keep working, since the data key doesn't change.
+    /// Before the store is modified, it is encrypted and opened back with the new password, so
+    /// we know the file we'll write can be opened. Once saved, the file itself is opened back
+    /// too, see `sync`.
+    pub fn change_master_password(&mut self,
+                                  master_password: &str,
+                                  keyfile: Option<SafeVec>,
+                                  kdf: Option<Kdf>)
+                                  -> Result<(), PasswordError> {
+        let index = self.master_slot();
+        let kdf = match self.slots[index].get_kind() {
+            SlotKind::Password { kdf: current_kdf, .. } => kdf.unwrap_or(current_kdf),
+            SlotKind::RecoveryKey => kdf.unwrap_or(Kdf::new()),
+        };
+        let master_slot = KeySlot::new_password(self.slots[index].get_label(),
+                                                master_password,
+                                                keyfile.as_ref().map(|k| k.deref()),
+                                                kdf,
+                                                self.key.deref(),
+                                                self.allow_expensive)?;
+
+        let mut slots = self.slots.clone();
+        slots[index] = master_slot;
+        let rekeyed = PasswordStore {
+            key: self.key.clone(),
+            slots: slots,
+            slot: index,
+            keyfile: keyfile.clone(),
+            allow_expensive: self.allow_expensive,
+            schema: self.schema.clone(),
+            modified: true,
+            new_master_password: Some(SafeString::new(master_password.to_owned())),
+        };
+
+        let reopened = PasswordStore::from_input(SafeString::new(master_password.to_owned()),
+                                                 keyfile,
+                                                 SafeVec::new(rekeyed.encrypt()?),
+                                                 self.allow_expensive)?;
+        if reopened.schema.passwords.len() != self.schema.passwords.len() {
+            return Err(PasswordError::CorruptionError);
+        }
+
+        *self = rekeyed;
+        Ok(())
+    }
+
+    pub fn get_slots(&self) -> &[KeySlot] {
+        self.slots.deref()
+    }
+
+    /// Index of the slot the store was opened with.
+    pub fn get_current_slot(&self) -> usize {
+        self.slot
+    }
+
+    fn has_slot(&self, label: &str) -> bool {
+        self.slots.iter().any(|slot| slot.get_label() == label)
+    }
+
+    fn add_slot(&mut self, slot: KeySlot) -> Result<(), PasswordError> {
+        if self.has_slot(slot.get_label()) {
+            return Err(PasswordError::SlotExistsError);
+        }
+        if self.slots.len() >= MAX_SLOTS {
+            return Err(PasswordError::TooManySlotsError);
+        }
+        self.slots.push(slot);
+        self.modified = true;
+        Ok(())
+    }
+
+    /// Adds a slot that opens the file with another password, and a keyfile if there is one.
+    pub fn add_password_slot(&mut self,
+                             label: &str,
+                             password: &str,
+                             keyfile: Option<SafeVec>)
+                             -> Result<(), PasswordError> {
+        let kdf = self.get_kdf().unwrap_or(Kdf::new());
+        let slot = KeySlot::new_password(label,
+                                         password,
+                                         keyfile.as_ref().map(|k| k.deref()),
+                                         kdf,
+                                         self.key.deref(),
+                                         self.allow_expensive)?;
+        self.add_slot(slot)
+    }
+
+    /// Adds a slot that opens the file with a new random recovery key.
+    ///
+    /// Returns the recovery key. It isn't stored anywhere, so this is the only chance to show
+    /// it, see `slot::format_recovery_key`.
+    pub fn add_recovery_key_slot(&mut self, label: &str) -> Result<SafeVec, PasswordError> {
+        let recovery_key = slot::generate_random_key()?;
+        let slot = KeySlot::new_recovery_key(label, recovery_key.deref(), self.key.deref())?;
+        self.add_slot(slot)?;
+        Ok(recovery_key)
+    }
+
+    /// Removes a slot, so its password or recovery key can no longer open the file.
+    ///
+    /// The slot the store was opened with can't be removed, and there must always be a
+    /// password slot left.
+    pub fn remove_slot(&mut self, label: &str) -> Result<(), PasswordError> {
+        let index = self.slots
+            .iter()
+            .position(|slot| slot.get_label() == label)
+            .ok_or(PasswordError::NoSuchSlotError)?;
+
+        if index == self.slot {
+            return Err(PasswordError::SlotInUseError);
+        }
+
+        let num_password_slots = self.slots
+            .iter()
+            .filter(|slot| slot.get_kind() != SlotKind::RecoveryKey)
+            .count();
+        if self.slots[index].get_kind() != SlotKind::RecoveryKey && num_password_slots == 1 {
+            return Err(PasswordError::LastPasswordSlotError);
+        }
+
+        self.slots.remove(index);
+        if index < self.slot {
+            self.slot -= 1;
+        }
+        self.modified = true;
+        Ok(())
+    }
+
+    /// Whether the store has changed since it was opened.
+    ///
+    /// If it hasn't, there is no need to save it.
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    /// Decrypts a backup of this store with its data key, see `read_backup`.
+    fn read_backup_schema(&self, input: &[u8]) -> Result<Schema, PasswordError> {
+        let header = read_header(input)?;
+        Ok(decrypt_schema(self.key.deref(), &header, input)?)
+    }
+
+    /// Reads the passwords of a backup of this store.
+    ///
+    /// The backup is opened with the data key of this store, which doesn't change when slots
+    /// are added, removed or changed. Backups made before the file was upgraded to this format
+    /// have another format or another key, so this fails with `WrongVersionError` or
+    /// `CorruptionError`. Those have to be opened on their own, with their master password, and
+    /// restored with `restore_store`.
+    pub fn read_backup(&self, input: &[u8]) -> Result<Vec<Password>, PasswordError> {
+        Ok(self.read_backup_schema(input)?.passwords)
+    }
+
+    /// Replaces the passwords and the trash with those of a backup of this store, see
+    /// `read_backup`.
+    ///
+    /// The slots are left as they are, so the store still opens the same way.
+    pub fn restore_backup(&mut self, input: &[u8]) -> Result<(), PasswordError> {
+        self.schema = self.read_backup_schema(input)?;
+        self.modified = true;
+        Ok(())
+    }
+
+    /// Replaces the passwords and the trash with those of another store, like a backup that
+    /// had to be opened on its own.
+    ///
+    /// The slots are left as they are, so the store still opens the same way.
+    pub fn restore_store(&mut self, backup: PasswordStore) {
+        self.schema = backup.schema;
+        self.modified = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    /// A store with a single password slot, for "pw". Its key derivation is as cheap as it gets,
+    /// so tests don't spend their time deriving keys.
+    fn test_store(passwords: Vec<Password>) -> PasswordStore {
+        let kdf = Kdf::Argon2id {
+            memory_kib: 8,
+            time: 1,
+            lanes: 1,
+        };
+        let key = slot::generate_random_key().unwrap();
+        let master_slot =
+            KeySlot::new_password(MASTER_SLOT_LABEL, "pw", None, kdf, key.deref(), false)
+                .unwrap();
+        PasswordStore::from_parts(key, vec![master_slot], 0, None, false, passwords).unwrap()
+    }
+
+    fn test_password(name: &str, username: &str) -> Password {
+        Password::new(name.to_owned(),
+                      username.to_owned(),
+                      SafeString::new(format!("{}-secret", name)))
+    }
+
+    /// A directory of its own for each test, so tests can run in parallel.
+    fn test_directory(name: &str) -> PathBuf {
+        let directory = env::temp_dir().join(format!("rooster-test-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn read_file(path: &Path) -> Vec<u8> {
+        let mut contents = Vec::new();
+        File::open(path).unwrap().read_to_end(&mut contents).unwrap();
+        contents
+    }
+
+    #[test]
+    fn sync_replaces_the_file() {
+        let directory = test_directory("sync-replaces");
+        let path = directory.join("passwords.rooster");
+
+        let mut store = test_store(vec![test_password("github", "alice")]);
+        store.sync(&path).unwrap();
+        store.add_password(test_password("gitlab", "alice")).unwrap();
+        store.sync(&path).unwrap();
+
+        let reopened = PasswordStore::from_input(SafeString::new("pw".to_owned()),
+                                                 None,
+                                                 SafeVec::new(read_file(&path)),
+                                                 false)
+            .unwrap();
+        assert_eq!(reopened.get_all_passwords().len(), 2);
+        assert!(!temporary_path(&path).unwrap().exists());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn sync_keeps_the_file_when_writing_fails() {
+        let directory = test_directory("sync-fails");
+        let path = directory.join("passwords.rooster");
+
+        let mut store = test_store(vec![test_password("github", "alice")]);
+        store.sync(&path).unwrap();
+        let original = read_file(&path);
+
+        // Something that isn't ours is where the temporary file goes, so the write fails
+        // before anything is written.
+        let temp_path = temporary_path(&path).unwrap();
+        fs::create_dir(&temp_path).unwrap();
+        File::create(temp_path.join("notes.txt")).unwrap();
+
+        store.add_password(test_password("gitlab", "alice")).unwrap();
+        assert!(store.sync(&path).is_err());
+
+        assert_eq!(read_file(&path), original);
+        assert!(temp_path.join("notes.txt").exists());
+        let reopened = PasswordStore::from_input(SafeString::new("pw".to_owned()),
+                                                 None,
+                                                 SafeVec::new(original),
+                                                 false)
+            .unwrap();
+        assert_eq!(reopened.get_all_passwords().len(), 1);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sync_does_not_follow_a_stale_temporary_file() {
+        use std::os::unix::fs::symlink;
+
+        let directory = test_directory("sync-stale");
+        let path = directory.join("passwords.rooster");
+        fs::create_dir(directory.join("elsewhere")).unwrap();
+
+        let temp_path = temporary_path(&path).unwrap();
+        symlink(directory.join("elsewhere").join("stolen"), &temp_path).unwrap();
+
+        let store = test_store(vec![test_password("github", "alice")]);
+        store.sync(&path).unwrap();
+
+        assert!(!directory.join("elsewhere").join("stolen").exists());
+        assert!(fs::symlink_metadata(&temp_path).is_err());
+        assert!(!fs::symlink_metadata(&path).unwrap().file_type().is_symlink());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sync_replaces_the_file_a_link_leads_to() {
+        use std::os::unix::fs::symlink;
+
+        let directory = test_directory("sync-link");
+        let synced = directory.join("Dropbox");
+        fs::create_dir(&synced).unwrap();
+        let target = synced.join("passwords.rooster");
+        let path = directory.join("passwords.rooster");
+
+        let mut store = test_store(vec![test_password("github", "alice")]);
+        store.sync(&target).unwrap();
+        symlink(&target, &path).unwrap();
+
+        store.add_password(test_password("gitlab", "alice")).unwrap();
+        store.sync(&path).unwrap();
+
+        assert!(fs::symlink_metadata(&path).unwrap().file_type().is_symlink());
+        let reopened = PasswordStore::from_input(SafeString::new("pw".to_owned()),
+                                                 None,
+                                                 SafeVec::new(read_file(&target)),
+                                                 false)
+            .unwrap();
+        assert_eq!(reopened.get_all_passwords().len(), 2);
+        assert!(!temporary_path(&path).unwrap().exists());
+        assert!(!temporary_path(&target).unwrap().exists());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn encode_pads_what_was_written() {
+        let mut password = test_password("github", "alice");
+        password.set_field(Field {
+            name: "url".to_owned(),
+            value: SafeString::new("https://github.com".to_owned()),
+            secret: false,
+        });
+        password.add_tag("work");
+        password.history.push(PreviousPassword {
+            password: SafeString::new("old".to_owned()),
+            replaced_at: 1,
+        });
+        password.pending = Some(PendingPassword {
+            password: SafeString::new("next".to_owned()),
+            staged_at: 2,
+        });
+        // Long enough to need more than the smallest padded length.
+        password.password = SafeString::new(String::from_utf8(vec![b'x'; 5000]).unwrap());
+
+        let mut store = test_store(vec![password, test_password("gitlab", "alice")]);
+        store.delete_password("gitlab", "alice").unwrap();
+
+        let encoded = store.schema.encode().unwrap();
+        assert_eq!(encoded.len(), 8192);
+
+        let decoded = Schema::decode(encoded.deref(), true).unwrap();
+        assert_eq!(decoded.passwords.len(), 1);
+        assert_eq!(decoded.trash.len(), 1);
+        let password = &decoded.passwords[0];
+        assert_eq!(password.password.len(), 5000);
+        assert_eq!(password.get_field("url").unwrap().value.deref(), "https://github.com");
+        assert!(password.has_tag("work"));
+        assert_eq!(password.history[0].password.deref(), "old");
+        assert_eq!(password.pending.as_ref().unwrap().password.deref(), "next");
+    }
+
+    fn test_pending_password(pending: &str) -> Password {
+        let mut password = test_password("github", "alice");
+        password.pending = Some(PendingPassword {
+            password: SafeString::new(pending.to_owned()),
+            staged_at: 1,
+        });
+        password
+    }
+
+    #[test]
+    fn confirm_pending_makes_the_pending_password_current() {
+        let mut store = test_store(vec![test_pending_password("next"),
+                                        test_password("gitlab", "alice")]);
+
+        for &(name, confirmed) in [("github", true), ("gitlab", false)].iter() {
+            store.change_password(name, "alice", &|old_password: Password| {
+                    let mut new_password = old_password.clone();
+                    assert_eq!(new_password.confirm_pending(), confirmed);
+                    new_password
+                })
+                .unwrap();
+        }
+
+        let store = reopen(&store, "pw").unwrap();
+        let password = store.get_password("github").unwrap();
+        assert_eq!(password.password.deref(), "next");
+        assert!(password.pending.is_none());
+        assert_eq!(password.history[0].password.deref(), "github-secret");
+        let password = store.get_password("gitlab").unwrap();
+        assert_eq!(password.password.deref(), "gitlab-secret");
+        assert!(password.history.is_empty());
+    }
+
+    #[test]
+    fn dropping_the_pending_password_keeps_the_current_one() {
+        let mut store = test_store(vec![test_pending_password("next")]);
+
+        store.change_password("github", "alice", &|old_password: Password| {
+                let mut new_password = old_password.clone();
+                new_password.pending = None;
+                new_password
+            })
+            .unwrap();
+
+        let store = reopen(&store, "pw").unwrap();
+        let password = store.get_password("github").unwrap();
+        assert_eq!(password.password.deref(), "github-secret");
+        assert!(password.pending.is_none());
+        assert!(password.history.is_empty());
+    }
+
+    #[test]
+    fn change_password_keeps_no_history_for_notes() {
+        let note = Password::new_note("recovery".to_owned(), SafeString::new("old".to_owned()));
+        let mut store = test_store(vec![test_password("github", "alice"), note]);
+
+        for &(name, username) in [("github", "alice"), ("recovery", "")].iter() {
+            store.change_password(name, username, &|old_password: Password| {
+                    let mut new_password = old_password.clone();
+                    new_password.password = SafeString::new("new".to_owned());
+                    new_password
+                })
+                .unwrap();
+        }
+
+        let password = store.get_password("github").unwrap();
+        assert_eq!(password.history[0].password.deref(), "github-secret");
+        let note = store.get_password("recovery").unwrap();
+        assert_eq!(note.password.deref(), "new");
+        assert!(note.history.is_empty());
+    }
+
+    fn names(store: &PasswordStore) -> Vec<String> {
+        store.get_all_passwords().iter().map(|p| p.name.clone()).collect()
+    }
+
+    #[test]
+    fn move_passwords_moves_the_whole_group() {
+        let mut store = test_store(vec![test_password("work/github", "alice"),
+                                        test_password("Work/aws/root", "alice"),
+                                        test_password("workshop", "alice"),
+                                        test_password("home/github", "alice")]);
+
+        assert_eq!(store.move_passwords("work/", "clients/acme").unwrap(), 2);
+        assert_eq!(names(&store),
+                   vec!["clients/acme/github", "clients/acme/aws/root", "workshop",
+                        "home/github"]);
+
+        assert_eq!(store.move_passwords("workshop", "home/workshop").unwrap(), 1);
+        assert_eq!(store.get_passwords_in_group("home").len(), 2);
+    }
+
+    #[test]
+    fn move_passwords_moves_nothing_when_a_name_is_taken() {
+        let mut store = test_store(vec![test_password("work/github", "alice"),
+                                        test_password("work/gitlab", "alice"),
+                                        test_password("home/GitLab", "alice")]);
+
+        match store.move_passwords("work", "home") {
+            Err(PasswordError::AppExistsError) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert_eq!(names(&store), vec!["work/github", "work/gitlab", "home/GitLab"]);
+
+        match store.move_passwords("school", "home") {
+            Err(PasswordError::NoSuchAppError) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn accounts_are_told_apart_even_when_they_read_the_same() {
+        let mut store = test_store(vec![test_password("a:b", "c"),
+                                        test_password("a", "b:c"),
+                                        test_password("github:alice", "bob"),
+                                        test_password("github", "alice")]);
+
+        // Typed by the user, these are ambiguous, so they have to pick one.
+        assert_eq!(store.get_accounts("a:b:c").len(), 2);
+        assert_eq!(store.get_accounts("github:alice").len(), 2);
+        assert!(store.get_password("a:b:c").is_none());
+
+        store.change_password("a", "b:c", &|old_password: Password| {
+                let mut new_password = old_password.clone();
+                new_password.password = SafeString::new("new".to_owned());
+                new_password
+            })
+            .unwrap();
+        let passwords = store.get_all_passwords();
+        assert_eq!(passwords[0].password.deref(), "a:b-secret");
+        assert_eq!(passwords[1].password.deref(), "new");
+
+        assert_eq!(store.delete_password("github", "alice").unwrap().name, "github");
+        assert_eq!(names(&store), vec!["a:b", "a", "github:alice"]);
+        assert_eq!(store.delete_password("A:B", "C").unwrap().username, "c");
+        assert_eq!(names(&store), vec!["a", "github:alice"]);
+
+        match store.delete_password("a", "c") {
+            Err(PasswordError::NoSuchAppError) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn restore_password_brings_back_the_last_deleted() {
+        let mut store = test_store(vec![test_password("github", "alice")]);
+
+        store.delete_password("github", "alice").unwrap();
+        assert!(store.get_all_passwords().is_empty());
+        store.add_password(test_password("github", "alice")).unwrap();
+        store.change_password("github", "alice", &|old_password: Password| {
+                let mut new_password = old_password.clone();
+                new_password.password = SafeString::new("newer".to_owned());
+                new_password
+            })
+            .unwrap();
+        store.delete_password("github", "alice").unwrap();
+        assert_eq!(store.get_trash().len(), 2);
+
+        let restored = store.restore_password("github").unwrap();
+        assert_eq!(restored.password.deref(), "newer");
+        assert_eq!(store.get_password("github").unwrap().password.deref(), "newer");
+        assert_eq!(store.get_trash().len(), 1);
+        assert_eq!(store.get_trash()[0].password.password.deref(), "github-secret");
+    }
+
+    #[test]
+    fn restore_password_refuses_ambiguous_and_taken_accounts() {
+        let mut store = test_store(vec![test_password("github", "alice"),
+                                        test_password("github", "bob")]);
+        store.delete_password("github", "alice").unwrap();
+        store.delete_password("github", "bob").unwrap();
+
+        match store.restore_password("github") {
+            Err(PasswordError::AmbiguousAppError) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert_eq!(store.restore_password("github:bob").unwrap().username, "bob");
+
+        store.add_password(test_password("github", "alice")).unwrap();
+        match store.restore_password("github:alice") {
+            Err(PasswordError::AppExistsError) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert_eq!(store.get_trash().len(), 1);
+        assert_eq!(store.get_all_passwords().len(), 2);
+
+        match store.restore_password("gitlab") {
+            Err(PasswordError::NoSuchAppError) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn purge_trash_removes_what_was_deleted_before() {
+        let mut store = test_store(vec![test_password("github", "alice"),
+                                        test_password("gitlab", "alice"),
+                                        test_password("bitbucket", "alice")]);
+        for (i, name) in ["github", "gitlab", "bitbucket"].iter().enumerate() {
+            store.delete_password(name, "alice").unwrap();
+            store.schema.trash[i].deleted_at = 100 * (i as Timestamp + 1);
+        }
+        store.modified = false;
+
+        assert_eq!(store.purge_trash(100), 0);
+        assert!(!store.modified);
+
+        assert_eq!(store.purge_trash(201), 2);
+        assert!(store.modified);
+        assert_eq!(store.get_trash().len(), 1);
+        assert_eq!(store.get_trash()[0].password.name, "bitbucket");
+
+        assert_eq!(store.purge_trash(Timestamp::max_value()), 1);
+        assert!(store.get_trash().is_empty());
+    }
+
+    #[test]
+    fn tags_match_whatever_their_case() {
+        let mut password = test_password("github", "alice");
+        assert!(password.add_tag("Work"));
+        assert!(!password.add_tag("work"));
+        assert!(!password.add_tag("WORK"));
+        assert_eq!(password.tags, vec!["Work"]);
+        assert!(password.has_tag("wOrK"));
+        assert!(!password.has_tag("home"));
+        assert!(password.has_tags(&[]));
+        assert!(password.has_tags(&["WORK".to_owned()]));
+        assert!(!password.has_tags(&["work".to_owned(), "home".to_owned()]));
+
+        assert!(!password.remove_tag("home"));
+        assert_eq!(password.tags, vec!["Work"]);
+        assert!(password.remove_tag("WORK"));
+        assert!(password.tags.is_empty());
+        assert!(!password.remove_tag("work"));
+    }
+
+    #[test]
+    fn search_passwords_keeps_what_has_every_tag() {
+        let mut github = test_password("github", "alice");
+        github.add_tag("work");
+        github.add_tag("2fa");
+        let mut gitlab = test_password("gitlab", "alice");
+        gitlab.add_tag("Work");
+        let store = test_store(vec![github, gitlab, test_password("gist", "alice")]);
+
+        let search = |query: &str, tags: &[&str]| -> Vec<String> {
+            let tags: Vec<String> = tags.iter().map(|t| t.to_string()).collect();
+            store.search_passwords(query, tags.deref()).iter().map(|p| p.name.clone()).collect()
+        };
+        assert_eq!(search("gi", &[]), vec!["github", "gitlab", "gist"]);
+        assert_eq!(search("gi", &["WORK"]), vec!["github", "gitlab"]);
+        assert_eq!(search("gi", &["work", "2FA"]), vec!["github"]);
+        assert_eq!(search("gitl", &["work"]), vec!["gitlab"]);
+        assert!(search("gi", &["home"]).is_empty());
+        assert!(search("gi", &["work", "home"]).is_empty());
+    }
+
+    /// Opens the store again from what it would write, with the given secret.
+    fn reopen(store: &PasswordStore, secret: &str) -> Result<PasswordStore, PasswordError> {
+        PasswordStore::from_input(SafeString::new(secret.to_owned()),
+                                  None,
+                                  SafeVec::new(store.encrypt().unwrap()),
+                                  false)
+    }
+
+    #[test]
+    fn every_slot_unlocks_the_store() {
+        let mut store = test_store(vec![test_password("github", "alice")]);
+        store.add_password_slot("phone", "pw2", None).unwrap();
+        let recovery_key = store.add_recovery_key_slot("safe").unwrap();
+        let recovery_key = slot::format_recovery_key(recovery_key.deref());
+
+        let opened = reopen(&store, "pw2").unwrap();
+        assert_eq!(opened.get_current_slot(), 1);
+        assert!(fixed_time_eq(opened.key.deref(), store.key.deref()));
+        assert_eq!(opened.get_all_passwords().len(), 1);
+
+        let opened = reopen(&store, recovery_key.deref()).unwrap();
+        assert_eq!(opened.get_current_slot(), 2);
+        assert!(opened.get_kdf().is_none());
+        assert_eq!(opened.get_all_passwords().len(), 1);
+
+        assert_eq!(reopen(&store, "pw").unwrap().get_current_slot(), 0);
+        match reopen(&store, "wrong") {
+            Err(PasswordError::DecryptionError) => {}
+            result => panic!("unexpected result: {:?}", result.map(|_| ())),
+        }
+        match store.add_password_slot("phone", "pw3", None) {
+            Err(PasswordError::SlotExistsError) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn remove_slot_keeps_a_way_to_open_the_store() {
+        let mut store = test_store(Vec::new());
+        let recovery_key = store.add_recovery_key_slot("safe").unwrap();
+        let recovery_key = slot::format_recovery_key(recovery_key.deref());
+
+        match store.remove_slot(MASTER_SLOT_LABEL) {
+            Err(PasswordError::SlotInUseError) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        let mut opened = reopen(&store, recovery_key.deref()).unwrap();
+        match opened.remove_slot(MASTER_SLOT_LABEL) {
+            Err(PasswordError::LastPasswordSlotError) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match opened.remove_slot("safe") {
+            Err(PasswordError::SlotInUseError) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match opened.remove_slot("missing") {
+            Err(PasswordError::NoSuchSlotError) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert_eq!(opened.get_slots().len(), 2);
+    }
+
+    #[test]
+    fn remove_slot_shifts_the_current_slot() {
+        let mut store = test_store(Vec::new());
+        store.add_password_slot("phone", "pw2", None).unwrap();
+        store.add_password_slot("laptop", "pw3", None).unwrap();
+
+        let mut opened = reopen(&store, "pw2").unwrap();
+        assert_eq!(opened.get_current_slot(), 1);
+
+        opened.remove_slot("laptop").unwrap();
+        assert_eq!(opened.get_current_slot(), 1);
+        opened.remove_slot(MASTER_SLOT_LABEL).unwrap();
+        assert_eq!(opened.get_current_slot(), 0);
+        assert_eq!(opened.get_slots()[opened.get_current_slot()].get_label(), "phone");
+        assert!(opened.is_modified());
+
+        // What is saved opens with the remaining slot only.
+        assert_eq!(reopen(&opened, "pw2").unwrap().get_current_slot(), 0);
+        assert!(reopen(&opened, "pw").is_err());
+        assert!(reopen(&opened, "pw3").is_err());
+    }
+
+    #[test]
+    fn from_parts_refuses_a_slot_it_does_not_have() {
+        let store = test_store(Vec::new());
+        let from_parts = |key: &[u8], slots: &[KeySlot], slot: usize| {
+            PasswordStore::from_parts(SafeVec::new(key.to_vec()),
+                                      slots.to_vec(),
+                                      slot,
+                                      None,
+                                      false,
+                                      Vec::new())
+                .map(|_| ())
+        };
+
+        assert!(from_parts(store.key.deref(), store.slots.deref(), 0).is_ok());
+        match from_parts(store.key.deref(), &[], 0) {
+            Err(PasswordError::NoSuchSlotError) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match from_parts(store.key.deref(), store.slots.deref(), 1) {
+            Err(PasswordError::NoSuchSlotError) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        let slots = vec![store.slots[0].clone(); MAX_SLOTS + 1];
+        match from_parts(store.key.deref(), slots.deref(), 0) {
+            Err(PasswordError::TooManySlotsError) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match from_parts(&store.key[1..], store.slots.deref(), 0) {
+            Err(PasswordError::CorruptionError) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn restore_backup_brings_back_the_trash_too() {
+        let mut store = test_store(vec![test_password("github", "alice")]);
+        let backup = store.encrypt().unwrap();
+
+        store.delete_password("github", "alice").unwrap();
+        store.restore_backup(&backup).unwrap();
+        assert_eq!(store.get_all_passwords().len(), 1);
+        assert!(store.get_trash().is_empty());
+
+        // Backups made before an upgrade have another key, or another format.
+        let other = test_store(Vec::new());
+        match store.restore_backup(&other.encrypt().unwrap()) {
+            Err(PasswordError::CorruptionError) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match store.restore_backup(&[0, 0, 0, 3, 0, 0, 0, 0]) {
+            Err(PasswordError::WrongVersionError) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        let mut other = test_store(vec![test_password("gitlab", "bob")]);
+        other.delete_password("gitlab", "bob").unwrap();
+        store.restore_store(other);
+        assert!(store.get_all_passwords().is_empty());
+        assert_eq!(store.get_trash()[0].password.name, "gitlab");
+        assert_eq!(store.get_slots().len(), 1);
+    }
+}
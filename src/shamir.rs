@@ -0,0 +1,224 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::rand::{Rng, OsRng};
+use super::safe_vec::SafeVec;
+use std::io::Result as IoResult;
+use std::ops::{Deref, DerefMut};
+
+/// One share of a secret split with Shamir's secret sharing.
+///
+/// Each byte of the secret is shared on its own, as the constant term of a random polynomial
+/// over GF(256). A share holds the value of every polynomial at `x`.
+#[derive(Clone, Debug)]
+pub struct Share {
+    /// How many shares are needed to rebuild the secret.
+    pub threshold: u8,
+    /// Where the polynomials were evaluated, never 0 since that's where the secret is.
+    pub x: u8,
+    pub y: SafeVec,
+}
+
+// Multiplies in GF(256), with the same reduction polynomial as AES, x^8 + x^4 + x^3 + x + 1.
+//
+// There is no branch or table lookup depending on the operands, so this runs in constant time.
+fn gf_mul(a: u8, b: u8) -> u8 {
+    let mut a = a;
+    let mut b = b;
+    let mut product = 0u8;
+    for _ in 0..8 {
+        product ^= a & 0u8.wrapping_sub(b & 1);
+        let carry = 0u8.wrapping_sub(a >> 7);
+        a = (a << 1) ^ (0x1b & carry);
+        b >>= 1;
+    }
+    product
+}
+
+// Inverts in GF(256), since a^254 is the inverse of a. The inverse of 0 is taken to be 0.
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1u8;
+    let mut power = a;
+    let mut exponent = 254u8;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = gf_mul(result, power);
+        }
+        power = gf_mul(power, power);
+        exponent >>= 1;
+    }
+    result
+}
+
+/// Splits a secret into `num_shares` shares, any `threshold` of which rebuild it.
+///
+/// Fewer than `threshold` shares tell nothing about the secret. The caller must make sure that
+/// `threshold` is at least 1 and at most `num_shares`.
+pub fn split(secret: &[u8], threshold: u8, num_shares: u8) -> IoResult<Vec<Share>> {
+    let mut rng = OsRng::new()?;
+
+    let mut shares: Vec<Share> = (1..num_shares as u16 + 1)
+        .map(|x| {
+            Share {
+                threshold: threshold,
+                x: x as u8,
+                y: SafeVec::new(vec![0u8; secret.len()]),
+            }
+        })
+        .collect();
+
+    // The coefficients of the polynomial, starting with the constant term.
+    let mut coefficients = SafeVec::new(vec![0u8; threshold as usize]);
+    for (i, b) in secret.iter().enumerate() {
+        coefficients[0] = *b;
+        rng.fill_bytes(&mut coefficients.deref_mut()[1..]);
+
+        for share in shares.iter_mut() {
+            // Horner's method, from the highest degree down.
+            let mut y = 0u8;
+            for coefficient in coefficients.iter().rev() {
+                y = gf_mul(y, share.x) ^ coefficient;
+            }
+            share.y[i] = y;
+        }
+    }
+
+    Ok(shares)
+}
+
+/// Rebuilds a secret from its shares.
+///
+/// Returns `None` if there are fewer shares than their threshold, if two of them are for the
+/// same `x`, or if they don't all look like they come from the same split. Shares from
+/// different splits that look alike still give garbage, there is no way to tell.
+pub fn combine(shares: &[Share]) -> Option<SafeVec> {
+    let first = match shares.first() {
+        Some(first) => first,
+        None => return None,
+    };
+    if shares.len() < first.threshold as usize {
+        return None;
+    }
+    for (i, share) in shares.iter().enumerate() {
+        if share.x == 0 || share.threshold != first.threshold || share.y.len() != first.y.len() ||
+           shares[..i].iter().any(|other| other.x == share.x) {
+            return None;
+        }
+    }
+    Some(interpolate(shares))
+}
+
+// Interpolates the polynomials of the shares at x = 0, whether there are enough shares or not.
+fn interpolate(shares: &[Share]) -> SafeVec {
+    let len = shares.first().map(|share| share.y.len()).unwrap_or(0);
+    let mut secret = SafeVec::new(vec![0u8; len]);
+
+    // Lagrange interpolation at x = 0. In GF(256), subtraction is the same as addition.
+    for (i, share) in shares.iter().enumerate() {
+        let mut basis = 1u8;
+        for (j, other) in shares.iter().enumerate() {
+            if i != j {
+                basis = gf_mul(basis, gf_mul(other.x, gf_inv(other.x ^ share.x)));
+            }
+        }
+
+        for (b, y) in secret.iter_mut().zip(share.y.deref().iter()) {
+            *b ^= gf_mul(basis, *y);
+        }
+    }
+
+    secret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &'static [u8] = b"the recovery key of the vault!!!";
+
+    #[test]
+    fn gf_mul_and_gf_inv_are_field_operations() {
+        // Examples from FIPS 197, which uses the same field.
+        assert_eq!(gf_mul(0x57, 0x83), 0xc1);
+        assert_eq!(gf_mul(0x57, 0x13), 0xfe);
+        assert_eq!(gf_inv(0x53), 0xca);
+        assert_eq!(gf_inv(0), 0);
+
+        for a in 0..256u16 {
+            let a = a as u8;
+            assert_eq!(gf_mul(a, 0), 0);
+            assert_eq!(gf_mul(a, 1), a);
+            if a != 0 {
+                assert_eq!(gf_mul(a, gf_inv(a)), 1);
+                assert_eq!(gf_inv(gf_inv(a)), a);
+            }
+            for b in 0..256u16 {
+                let b = b as u8;
+                assert_eq!(gf_mul(a, b), gf_mul(b, a));
+                assert_eq!(gf_mul(a, b ^ 0x1d), gf_mul(a, b) ^ gf_mul(a, 0x1d));
+            }
+        }
+    }
+
+    #[test]
+    fn any_threshold_shares_rebuild_the_secret() {
+        let shares = split(SECRET, 3, 5).unwrap();
+        assert_eq!(shares.iter().map(|share| share.x).collect::<Vec<u8>>(),
+                   vec![1, 2, 3, 4, 5]);
+
+        let mut num_subsets = 0;
+        for i in 0..5 {
+            for j in i + 1..5 {
+                for k in j + 1..5 {
+                    // In any order.
+                    let subset = vec![shares[k].clone(), shares[i].clone(), shares[j].clone()];
+                    assert_eq!(combine(subset.deref()).unwrap().deref(), SECRET);
+                    num_subsets += 1;
+                }
+            }
+        }
+        assert_eq!(num_subsets, 10);
+
+        // More shares than needed work too.
+        assert_eq!(combine(shares.deref()).unwrap().deref(), SECRET);
+    }
+
+    #[test]
+    fn fewer_shares_than_the_threshold_do_not_rebuild_the_secret() {
+        let shares = split(SECRET, 3, 5).unwrap();
+        for i in 0..5 {
+            for j in i + 1..5 {
+                let subset = vec![shares[i].clone(), shares[j].clone()];
+                assert!(combine(subset.deref()).is_none());
+                assert!(interpolate(subset.deref()).deref() != SECRET);
+            }
+        }
+        assert!(combine(&[]).is_none());
+    }
+
+    #[test]
+    fn combine_rejects_shares_that_do_not_fit_together() {
+        let shares = split(SECRET, 2, 3).unwrap();
+
+        let duplicate = vec![shares[0].clone(), shares[0].clone()];
+        assert!(combine(duplicate.deref()).is_none());
+
+        let mut at_zero = shares[1].clone();
+        at_zero.x = 0;
+        assert!(combine(&[shares[0].clone(), at_zero]).is_none());
+
+        let other_split = split(SECRET, 3, 3).unwrap();
+        assert!(combine(&[shares[0].clone(), other_split[1].clone()]).is_none());
+    }
+}
@@ -0,0 +1,87 @@
+// Copyright 2014-2017 The Rooster Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Seconds since the Unix epoch, negative before it.
+///
+/// 64 bits are enough for dates far beyond 2038.
+pub type Timestamp = i64;
+
+/// The current time.
+///
+/// If the system clock is set before 1970, that's what we get, rather than a failure.
+pub fn now() -> Timestamp {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as Timestamp,
+        Err(err) => -(err.duration().as_secs() as Timestamp),
+    }
+}
+
+/// Splits a timestamp into a UTC date and time: year, month, day, hours, minutes and seconds.
+pub fn to_utc(timestamp: Timestamp) -> (i64, i64, i64, i64, i64, i64) {
+    // Rounds towards the past, so dates before 1970 come out right too.
+    let days = timestamp.div_euclid(86400);
+    let seconds = timestamp.rem_euclid(86400);
+
+    // Converts days since 1970-01-01 to a date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = (if z >= 0 { z } else { z - 146096 }) / 146097;
+    let day_of_era = z - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 -
+                       day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day, seconds / 3600, seconds % 3600 / 60, seconds % 60)
+}
+
+/// Formats a timestamp as a UTC date, for instance `2017-03-01 10:00:00 UTC`.
+pub fn format(timestamp: Timestamp) -> String {
+    let (year, month, day, hours, minutes, seconds) = to_utc(timestamp);
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+            year,
+            month,
+            day,
+            hours,
+            minutes,
+            seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_utc_splits_timestamps() {
+        assert_eq!(to_utc(0), (1970, 1, 1, 0, 0, 0));
+        assert_eq!(to_utc(1488362400), (2017, 3, 1, 10, 0, 0));
+        assert_eq!(to_utc(-1), (1969, 12, 31, 23, 59, 59));
+        assert_eq!(to_utc(951782400), (2000, 2, 29, 0, 0, 0));
+    }
+
+    #[test]
+    fn to_utc_handles_the_extremes() {
+        assert_eq!(to_utc(i64::MIN).5, 52);
+        assert_eq!(to_utc(i64::MAX).5, 7);
+    }
+}